@@ -9,7 +9,8 @@ extern crate arrayfire as af;
 // use std::f32;
 
 extern crate optical_music_recognition as omr;
-use omr::ffmpeg_camera::ffmpeg_camera;
+use omr::ffmpeg_camera::frame_source;
+use omr::ffmpeg_camera::frame_recording;
 use omr::ffmpeg_camera::ffmpeg_utils;
 use omr::ffmpeg_camera::image::Image;
 use omr::ffmpeg_camera::image_ycbcr;
@@ -29,6 +30,7 @@ use time::Duration;
 use time::SteadyTime;
 
 use std::cell::RefCell;
+use std::env;
 
 fn get_fake_webcam_frame() -> image_ycbcr::Image {
     // Fake webcam frame:
@@ -178,8 +180,16 @@ fn main() {
     // let (img_w, img_h) = (1280, 720);
     // let (img_w, img_h) = (1920, 1080);
 
-    let mut camera = ffmpeg_camera::FfmpegCamera::get_best((img_w, img_h))
-        .expect("Failed to open camera.");
+    // Frame source specification (see frame_source::open_source), e.g. "camera",
+    // "video:score.mp4", "images:scans/", or "synthetic".
+    // "recording:<file>" replays a recording made by passing a file name as the second argument.
+    let source_spec = env::args().nth(1).unwrap_or(String::from("camera"));
+    let mut source = frame_source::open_source(&source_spec, (img_w, img_h))
+        .expect("Failed to open frame source.");
+    if let Some(recording_file_name) = env::args().nth(2) {
+        source = Box::new(frame_recording::RecordedSource::new(source, &recording_file_name)
+            .expect("Failed to create frame recording."));
+    }
 
     let window_dims = (img_w, img_h);
     println!("Create display:");
@@ -250,7 +260,11 @@ fn main() {
         // let mut webcam_frame = conversion_frame.convert_uyvy_ycbcr(&uyvy_frame).unwrap();
 
         // Get nv12 frame:
-        let mut webcam_frame = camera.get_image_nv12().unwrap();
+        let mut webcam_frame = match source.next_frame() {
+            Ok(frame) => frame.image,
+            Err(ref err) if err.is_end_of_stream() => return,
+            Err(err) => panic!("Failed to read frame: {}", err),
+        };
 
         // println!("webcam_frame: {}x{}", webcam_frame.width(), webcam_frame.height());
         // println!("dims: {:?}", webcam_frame.af_data.dims().unwrap());
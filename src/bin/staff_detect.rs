@@ -1,20 +1,22 @@
 #[macro_use]
 extern crate glium;
 extern crate nalgebra as na;
+extern crate rand;
 extern crate time;
 // extern crate image;
 
 // use std::f32;
 
 extern crate optical_music_recognition as omr;
-use omr::ffmpeg_camera::ffmpeg_camera;
+use omr::ffmpeg_camera::frame_source;
 use omr::ffmpeg_camera::image::Image;
 use omr::drawing;
-use omr::math;
 use omr::geometry;
 use omr::geometry as gm;
 use omr::detection::ransac::staff_cross::StaffCrossLineModel;
+use omr::detection::ransac::staff_curve::StaffCurveModel;
 use omr::detection::scanning::staff_cross::StaffCross;
+use omr::detection::binarisation::Binarisation;
 
 // use std::io::Cursor;
 use glium::DisplayBuild;
@@ -25,6 +27,7 @@ use time::Duration;
 use time::SteadyTime;
 
 use std::cell::RefCell;
+use std::env;
 
 fn main() {
     // let mut camera =
@@ -37,8 +40,26 @@ fn main() {
     // let (img_w, img_h) = (1280, 720);
     // let (img_w, img_h) = (1920, 1080);
 
-    let mut camera = ffmpeg_camera::FfmpegCamera::get_best((img_w, img_h))
-        .expect("Failed to open camera.");
+    let mut args : Vec<String> = env::args().skip(1).collect();
+
+    // Fit curved staves (e.g. on photos of bound books) instead of straight ones:
+    let mut curved = false;
+    if let Some(i) = args.iter().position(|arg| arg == "--curved") {
+        curved = true;
+        args.remove(i);
+    }
+
+    // Frame source specification (see frame_source::open_source), e.g. "camera",
+    // "video:score.mp4", "images:scans/", or "synthetic".
+    let source_spec = args.get(0).cloned().unwrap_or(String::from("camera"));
+    let mut source = frame_source::open_source(&source_spec, (img_w, img_h))
+        .expect("Failed to open frame source.");
+
+    // Binarisation method (see Binarisation::from_name), e.g. "otsu" or "sauvola".
+    let binarisation = match args.get(1) {
+        Some(name) => Binarisation::from_name(name).expect("Unknown binarisation method."),
+        None => Binarisation::default(),
+    };
 
     let display = glium::glutin::WindowBuilder::new()
         // .with_dimensions(1280, 720)
@@ -79,7 +100,11 @@ fn main() {
     let mut frame_start_time = SteadyTime::now();
     loop {
 
-        let webcam_frame = camera.get_image_nv12().unwrap();
+        let webcam_frame = match source.next_frame() {
+            Ok(frame) => frame.image,
+            Err(ref err) if err.is_end_of_stream() => return,
+            Err(err) => panic!("Failed to read frame: {}", err),
+        };
         // let webcam_frame = camera.get_image_uyvy().unwrap();
         // let mut webcam_frame = conversion_frame.convert_uyvy_ycbcr(&webcam_frame).unwrap();
         // let webcam_frame = camera.get_image_ycbcr().unwrap();
@@ -104,10 +129,22 @@ fn main() {
 
         let fitting_start_time = SteadyTime::now();
 
-        // Scan entire image for StaffCross points:
-        // let num_scan_lines = std::cmp::min(640, img_w / 2);
-        let num_scan_lines = 320;
-        let cross_points = omr::detection::scanning::staff_cross::scan_entire_image(&webcam_frame, num_scan_lines);
+        // Detection only requires the luma plane:
+        let grey_frame = webcam_frame.luma_image();
+
+        // Scan entire image for StaffCross points, at several angles so that rotated staves are
+        // also found:
+        let binarizer = binarisation.build(&grey_frame);
+        let (_, staff_metrics) = omr::detection::runs::histogram::staff_metrics(&grey_frame, &*binarizer);
+        let scan_params = omr::detection::scanning::staff_cross::ScanParams {
+            staff_metrics: Some(staff_metrics),
+            .. Default::default()
+        };
+        let cross_points = omr::detection::scanning::staff_cross::scan_image_at_angles(&grey_frame, &*binarizer, &scan_params);
+        let sample_params = omr::detection::refinement::SampleParams {
+            binarizer: Some(&*binarizer),
+            .. Default::default()
+        };
 
         // Draw detected StaffCross points:
         // for cross in &cross_points {
@@ -117,7 +154,7 @@ fn main() {
 
 
         // // Draw segments:
-        // let segments = omr::detection::scanning::segment::scan_entire_image(&webcam_frame, num_scan_lines);
+        // let segments = omr::detection::scanning::segment::scan_entire_image(&grey_frame, num_scan_lines);
         // let x = 256;
         // for segment in segments.iter() {
         //     // let col = [(i*71 % 255) as f32 / 255.0, 0.5 * (i*333 % 255) as f32 / 255.0, 0.0, 0.0];
@@ -154,143 +191,179 @@ fn main() {
         // Run RANSAC on the StaffCross points to find a line:
         let params = omr::detection::ransac::RansacParams {
             num_iterations: num_iterations,
-            max_distance: 111.0, // not currently used
             min_inliers: 15,
+            // Sample the most regular crosses first, and prefer lines that pass close to the crosses:
+            sampling: omr::detection::ransac::RansacSampling::Prosac,
+            scoring: omr::detection::ransac::RansacScoring::Msac,
+            local_optimisation_iterations: 5,
+            thread_pool: Default::default(),
         };
         // let maybe_line = omr::detection::ransac::ransac::<StaffCrossLineModel,_,_>(params, &cross_points);
         // let state = omr::detection::ransac::ransac::<StaffCrossLineModel,_,_>(&params, &cross_points);
         // draw_frame.draw_ransac_state(&mut target, &webcam_frame, &state);
 
-        let states = omr::detection::ransac::ransac_multiple::<StaffCrossLineModel,_,_>(&params, &cross_points);
-        for state in &states {
-            // draw_frame.draw_ransac_state(&mut target, &webcam_frame, &state);
-
-            let centres : Vec<na::Vector2<f32>> = state.inliers.iter()
-                // .take(5)
-                .map(|c| c.centre()).collect();
-
-            let best_line = math::fit_line(&centres);
-            let p1 = webcam_frame.opengl_coords_for_point(best_line.a);
-            let p2 = webcam_frame.opengl_coords_for_point(best_line.b);
-            // draw_frame.draw_line_extended(&mut target, p1, p2, 3.0, [0.3, 0.3, 0.1, 1.0]);
-
-            let mut is_staff = false;
-            if let Some(ref detected_line) = state.model {
-                // is_staff = omr::detection::refinement::refine_detected_staff(&detected_line, &state.inliers);
-
-                // detected_line
-                // best_line
-                let inliers = &state.inliers;
-
-                let (t_min, t_max) = best_line.screen_entry_exit_times(webcam_frame.width as f32, webcam_frame.height as f32);
-                // let p_min = webcam_frame.opengl_coords_for_point(best_line.point_at_time(t_min));
-                // let p_max = webcam_frame.opengl_coords_for_point(best_line.point_at_time(t_max));
-                // draw_frame.draw_line(&mut target, p_min, p_min*0.9+p_max*0.1, 10.0, [1.0, 1.0, 0.5, 1.0]);
-                // draw_frame.draw_line(&mut target, p_max, p_min*0.1+p_max*0.9, 10.0, [1.0, 1.0, 0.5, 1.0]);
-
-                let normal = best_line.normal();
-
-                let avg_space_width = inliers.iter().fold(0.0, |sum, pt| sum + pt.average_space_width(&best_line)) / inliers.len() as f32;
-                let avg_line_width = inliers.iter().fold(0.0, |sum, pt| sum + pt.average_line_width(&best_line)) / inliers.len() as f32;
-
-                let line_sep = avg_space_width + avg_line_width;
-
-                let staff = geometry::staff::Staff::new(
-                    best_line.a,
-                    best_line.b,
-                    avg_line_width,
-                    avg_space_width
-                );
-
-                // let num = 100;
-                // for i in 0..num {
-                let mut t = t_min;
-                let step_size = staff.line_sep() * 0.5;
-                while t + step_size < t_max {
-                    t += step_size;
-
-                    // Sample lines:
-                    let mut line_avg = 0.0;
-                    for pt in staff.perpendicular_samples(t, 5, line_sep) {
-                        let brightness = webcam_frame.sample_point(pt).y as f32 / 255.0;
-                        line_avg += brightness.round();
-
-                        let draw_pt = webcam_frame.opengl_coords_for_point(pt);
-
-                        let colour = if brightness > 0.5 {[0.0, 0.5, 0.0, 1.0]} else {[0.0, 0.0, 0.5, 1.0]};
-                        // draw_frame.draw_point(&mut target, draw_pt, 1.0, colour);
+        // Note: A fixed seed makes detections reproducible.
+        let multi_params = omr::detection::ransac::MultiRansacParams::default();
+        let mut rng = rand::XorShiftRng::new_unseeded();
+        let staff_model = StaffCrossLineModel {
+            staff_metrics: Some(staff_metrics),
+            .. Default::default()
+        };
+        if curved {
+            let curve_model = StaffCurveModel {
+                num_segments: 3,
+                thresholds: staff_model,
+                .. Default::default()
+            };
+            let states = omr::detection::ransac::ransac_multiple(
+                &curve_model, &params, &multi_params, &cross_points, &mut rng);
+            for state in &states {
+                let staff = match state.model {
+                    Some(ref staff) => staff,
+                    None => continue,
+                };
+
+                let (candidate_segments, _) = omr::detection::refinement::partition_staff_with(&grey_frame, staff, &sample_params);
+                let staff_segments = candidate_segments.iter()
+                    .filter(|segment| omr::detection::refinement::staff_segment_is_valid_with(&grey_frame, *segment, &sample_params));
+                for segment in staff_segments {
+                    draw_frame.draw_curved_staff_in_image(&mut target, &webcam_frame, segment, [0.8, 0.3, 1.0, 1.0]);
+                }
+            }
+        } else {
+            let states = omr::detection::ransac::ransac_multiple(
+                &staff_model, &params, &multi_params, &cross_points, &mut rng);
+            for state in &states {
+                // draw_frame.draw_ransac_state(&mut target, &webcam_frame, &state);
+
+                // Note: The model has been refined to fit all of its inliers.
+                let best_line = match state.model {
+                    Some(ref model) => model.line(),
+                    None => continue,
+                };
+                let p1 = webcam_frame.opengl_coords_for_point(best_line.a);
+                let p2 = webcam_frame.opengl_coords_for_point(best_line.b);
+                // draw_frame.draw_line_extended(&mut target, p1, p2, 3.0, [0.3, 0.3, 0.1, 1.0]);
+
+                let mut is_staff = false;
+                if let Some(ref detected_line) = state.model {
+                    // is_staff = omr::detection::refinement::refine_detected_staff(&detected_line, &state.inliers);
+
+                    // detected_line
+                    // best_line
+                    let inliers = &state.inliers;
+
+                    let (t_min, t_max) = best_line.screen_entry_exit_times(webcam_frame.width as f32, webcam_frame.height as f32);
+                    // let p_min = webcam_frame.opengl_coords_for_point(best_line.point_at_time(t_min));
+                    // let p_max = webcam_frame.opengl_coords_for_point(best_line.point_at_time(t_max));
+                    // draw_frame.draw_line(&mut target, p_min, p_min*0.9+p_max*0.1, 10.0, [1.0, 1.0, 0.5, 1.0]);
+                    // draw_frame.draw_line(&mut target, p_max, p_min*0.1+p_max*0.9, 10.0, [1.0, 1.0, 0.5, 1.0]);
+
+                    let normal = best_line.normal();
+
+                    let avg_space_width = inliers.iter().fold(0.0, |sum, pt| sum + pt.average_space_width(&best_line)) / inliers.len() as f32;
+                    let avg_line_width = inliers.iter().fold(0.0, |sum, pt| sum + pt.average_line_width(&best_line)) / inliers.len() as f32;
+
+                    let line_sep = avg_space_width + avg_line_width;
+
+                    let staff = geometry::staff::Staff::new(
+                        best_line.a,
+                        best_line.b,
+                        avg_line_width,
+                        avg_space_width,
+                        inliers[0].num_lines()
+                    );
+
+                    // let num = 100;
+                    // for i in 0..num {
+                    let mut t = t_min;
+                    let step_size = staff.line_sep() * 0.5;
+                    while t + step_size < t_max {
+                        t += step_size;
+
+                        // Sample lines:
+                        let mut line_avg = 0.0;
+                        for pt in staff.perpendicular_samples(t, staff.num_lines, line_sep) {
+                            let brightness = webcam_frame.sample_point_luma(pt) as f32 / 255.0;
+                            line_avg += brightness.round();
+
+                            let draw_pt = webcam_frame.opengl_coords_for_point(pt);
+
+                            let colour = if brightness > 0.5 {[0.0, 0.5, 0.0, 1.0]} else {[0.0, 0.0, 0.5, 1.0]};
+                            // draw_frame.draw_point(&mut target, draw_pt, 1.0, colour);
+                        }
+                        line_avg /= 5.0;
+
+                        // Sample spaces:
+                        let mut space_avg = 0.0;
+                        for pt in omr::detection::refinement::staff_space_samples(&staff, t) {
+                            let brightness = webcam_frame.sample_point_luma(pt) as f32 / 255.0;
+                            space_avg += brightness.round();
+
+                            let draw_pt = webcam_frame.opengl_coords_for_point(pt);
+                            // draw_frame.draw_point(&mut target, draw_pt, 1.0, [0.8, 0.0, 1.0, 1.0]);
+                        }
+                        space_avg /= 4.0;
+
+                        // Blank spaces:
+                        let num_samples = 20;
+                        let sample_sep = 1.2 * line_sep * 2.0 / (num_samples as f32 * 0.5);
+                        let mut blank_avg = 0.0;
+                        for pt in staff.perpendicular_samples(t, num_samples, sample_sep) {
+                            let brightness = webcam_frame.sample_point_luma(pt) as f32 / 255.0;
+                            blank_avg += brightness.round();
+                            let draw_pt = webcam_frame.opengl_coords_for_point(pt);
+                            // draw_frame.draw_point(&mut target, draw_pt, 1.0, [0.2, 0.2, 0.2, 1.0]);
+                        }
+                        blank_avg /= num_samples as f32;
+
+                        let class = omr::detection::refinement::classify_staff_sample(line_avg, space_avg, blank_avg);
+
+                        let p_t = best_line.point_at_time(t);
+                        let draw_pt1 = webcam_frame.opengl_coords_for_point(p_t+normal*line_sep*2.0);
+                        let draw_pt2 = webcam_frame.opengl_coords_for_point(p_t-normal*line_sep*2.0);
+
+                        if class == omr::detection::refinement::StaffEvidenceClass::Blank {
+                            // draw_frame.draw_line(&mut target, draw_pt1, draw_pt2, 1.0, [0.0, 0.0, 0.0, 1.0]);
+                        }
+                        if class == omr::detection::refinement::StaffEvidenceClass::Strong {
+                            // draw_frame.draw_line(&mut target, draw_pt1, draw_pt2, 1.0, [1.0, 0.3, 0.0, 1.0]);
+                        }
+                        if class == omr::detection::refinement::StaffEvidenceClass::Partial {
+                            // draw_frame.draw_line(&mut target, draw_pt1, draw_pt2, 1.0, [1.0, 0.8, 0.0, 1.0]);
+                        }
+                        if class == omr::detection::refinement::StaffEvidenceClass::Weak {
+                            // draw_frame.draw_line(&mut target, draw_pt1, draw_pt2, 1.0, [0.3, 0.6, 0.0, 1.0]);
+                        }
+                        if class == omr::detection::refinement::StaffEvidenceClass::Negative {
+                            // draw_frame.draw_line(&mut target, draw_pt1, draw_pt2, 1.0, [0.0, 0.0, 1.0, 1.0]);
+                        }
+                        // if class == omr::detection::refinement::StaffEvidenceClass::None {
+                        //     draw_frame.draw_line(&mut target, draw_pt1, draw_pt2, 1.0, [0.0, 0.5, 1.0, 1.0]);
+                        // }
                     }
-                    line_avg /= 5.0;
-
-                    // Sample spaces:
-                    let mut space_avg = 0.0;
-                    for pt in staff.perpendicular_samples(t, 4, line_sep) {
-                        let brightness = webcam_frame.sample_point(pt).y as f32 / 255.0;
-                        space_avg += brightness.round();
 
-                        let draw_pt = webcam_frame.opengl_coords_for_point(pt);
-                        // draw_frame.draw_point(&mut target, draw_pt, 1.0, [0.8, 0.0, 1.0, 1.0]);
+                    let (candidate_segments, blank_segments) = omr::detection::refinement::partition_staff_with(&grey_frame, &staff, &sample_params);
+                    for part in &candidate_segments {
+                        let staff_pt1 = part.point_at_time(0.0);
+                        let staff_pt2 = part.point_at_time(part.length);
+                        let draw_pt1 = webcam_frame.opengl_coords_for_point(staff_pt1);
+                        let draw_pt2 = webcam_frame.opengl_coords_for_point(staff_pt2);
+                        draw_frame.draw_line(&mut target, draw_pt1, draw_pt2, 1.0, [1.0, 1.0, 1.0, 1.0]);
                     }
-                    space_avg /= 4.0;
-
-                    // Blank spaces:
-                    let num_samples = 20;
-                    let sample_sep = 1.2 * line_sep * 2.0 / (num_samples as f32 * 0.5);
-                    let mut blank_avg = 0.0;
-                    for pt in staff.perpendicular_samples(t, num_samples, sample_sep) {
-                        let brightness = webcam_frame.sample_point(pt).y as f32 / 255.0;
-                        blank_avg += brightness.round();
-                        let draw_pt = webcam_frame.opengl_coords_for_point(pt);
-                        // draw_frame.draw_point(&mut target, draw_pt, 1.0, [0.2, 0.2, 0.2, 1.0]);
+                    for part in blank_segments {
+                        let staff_pt1 = part.point_at_time(0.0);
+                        let staff_pt2 = part.point_at_time(part.length);
+                        let draw_pt1 = webcam_frame.opengl_coords_for_point(staff_pt1);
+                        let draw_pt2 = webcam_frame.opengl_coords_for_point(staff_pt2);
+                        draw_frame.draw_line(&mut target, draw_pt1, draw_pt2, 1.0, [0.0, 0.0, 0.0, 1.0]);
                     }
-                    blank_avg /= num_samples as f32;
-
-                    let class = omr::detection::refinement::classify_staff_sample(line_avg, space_avg, blank_avg);
 
-                    let p_t = best_line.point_at_time(t);
-                    let draw_pt1 = webcam_frame.opengl_coords_for_point(p_t+normal*line_sep*2.0);
-                    let draw_pt2 = webcam_frame.opengl_coords_for_point(p_t-normal*line_sep*2.0);
-
-                    if class == omr::detection::refinement::StaffEvidenceClass::Blank {
-                        // draw_frame.draw_line(&mut target, draw_pt1, draw_pt2, 1.0, [0.0, 0.0, 0.0, 1.0]);
-                    }
-                    if class == omr::detection::refinement::StaffEvidenceClass::Strong {
-                        // draw_frame.draw_line(&mut target, draw_pt1, draw_pt2, 1.0, [1.0, 0.3, 0.0, 1.0]);
+                    let staff_segments = candidate_segments.iter()
+                        .filter(|segment| omr::detection::refinement::staff_segment_is_valid_with(&grey_frame, *segment, &sample_params));
+                    for segment in staff_segments {
+                        draw_frame.draw_staff_in_image(&mut target, &webcam_frame, &segment, [0.8, 0.3, 1.0, 1.0]);
                     }
-                    if class == omr::detection::refinement::StaffEvidenceClass::Partial {
-                        // draw_frame.draw_line(&mut target, draw_pt1, draw_pt2, 1.0, [1.0, 0.8, 0.0, 1.0]);
-                    }
-                    if class == omr::detection::refinement::StaffEvidenceClass::Weak {
-                        // draw_frame.draw_line(&mut target, draw_pt1, draw_pt2, 1.0, [0.3, 0.6, 0.0, 1.0]);
-                    }
-                    if class == omr::detection::refinement::StaffEvidenceClass::Negative {
-                        // draw_frame.draw_line(&mut target, draw_pt1, draw_pt2, 1.0, [0.0, 0.0, 1.0, 1.0]);
-                    }
-                    // if class == omr::detection::refinement::StaffEvidenceClass::None {
-                    //     draw_frame.draw_line(&mut target, draw_pt1, draw_pt2, 1.0, [0.0, 0.5, 1.0, 1.0]);
-                    // }
-                }
-
-                let (candidate_segments, blank_segments) = omr::detection::refinement::partition_staff(&webcam_frame, &staff);
-                for part in &candidate_segments {
-                    let staff_pt1 = part.point_at_time(0.0);
-                    let staff_pt2 = part.point_at_time(part.length);
-                    let draw_pt1 = webcam_frame.opengl_coords_for_point(staff_pt1);
-                    let draw_pt2 = webcam_frame.opengl_coords_for_point(staff_pt2);
-                    draw_frame.draw_line(&mut target, draw_pt1, draw_pt2, 1.0, [1.0, 1.0, 1.0, 1.0]);
-                }
-                for part in blank_segments {
-                    let staff_pt1 = part.point_at_time(0.0);
-                    let staff_pt2 = part.point_at_time(part.length);
-                    let draw_pt1 = webcam_frame.opengl_coords_for_point(staff_pt1);
-                    let draw_pt2 = webcam_frame.opengl_coords_for_point(staff_pt2);
-                    draw_frame.draw_line(&mut target, draw_pt1, draw_pt2, 1.0, [0.0, 0.0, 0.0, 1.0]);
-                }
-
-                let staff_segments = candidate_segments.iter()
-                    .filter(|segment| omr::detection::refinement::staff_segment_is_valid(&webcam_frame, &segment));
-                for segment in staff_segments {
-                    draw_frame.draw_staff_in_image(&mut target, &webcam_frame, &segment, [0.8, 0.3, 1.0, 1.0]);
                 }
             }
         }
@@ -0,0 +1,201 @@
+extern crate nalgebra as na;
+extern crate rand;
+
+extern crate optical_music_recognition as omr;
+use omr::ffmpeg_camera::image_file;
+use omr::ffmpeg_camera::image::Image;
+use omr::ffmpeg_camera::image_pyramid::ImagePyramid;
+use omr::ffmpeg_camera::image_view::Region;
+use omr::detection::binarisation::Binarisation;
+use omr::detection::scanning::staff_cross::ScanParams;
+use omr::detection::ransac::staff_cross::StaffCrossLineModel;
+use omr::detection::ransac::staff_curve::StaffCurveModel;
+use omr::detection::refinement::SampleParams;
+use omr::geometry::staff::StaffPath;
+
+use std::env;
+
+// Parses a region of the form "x,y,width,height".
+fn parse_region(region_str : &str) -> Option<Region> {
+    let values : Vec<usize> = region_str.split(',').filter_map(|v| v.trim().parse().ok()).collect();
+    if values.len() != 4 {
+        return None;
+    }
+
+    // Empty regions contain nothing to scan:
+    if values[2] == 0 || values[3] == 0 {
+        return None;
+    }
+
+    Some(Region::new(values[0], values[1], values[2], values[3]))
+}
+
+// Prints the segments of a candidate staff that are supported by the image.
+fn print_staff_segments<I: Image, S: StaffPath>(image: &I, staff: &S, sample_params: &SampleParams) {
+    let (candidate_segments, _) = omr::detection::refinement::partition_staff_with(image, staff, sample_params);
+    let staff_segments = candidate_segments.iter()
+        .filter(|segment| omr::detection::refinement::staff_segment_is_valid_with(image, *segment, sample_params));
+    for segment in staff_segments {
+        let p1 = segment.point_at_time(0.0);
+        let p2 = segment.point_at_time(segment.length());
+        println!("    staff: ({}, {}) -> ({}, {}), lines: {}, line width: {}, space width: {}",
+            p1[0], p1[1], p2[0], p2[1], segment.num_lines(), segment.line_width(), segment.space_width());
+    }
+}
+
+// Runs staff detection on each of the image files given on the command line, printing the
+// detected staff segments.
+// If a region is given (with --region x,y,width,height), only that part of each image is scanned.
+// The binarisation method can be chosen with --threshold <method> (see Binarisation::from_name).
+// Staves with other numbers of lines can be found with --lines <counts> (e.g. --lines 4,5,6).
+// Curved staves are fitted instead of straight ones with --curved.
+fn main() {
+    let mut args : Vec<String> = env::args().skip(1).collect();
+
+    let mut line_counts = vec![5];
+    if let Some(i) = args.iter().position(|arg| arg == "--lines") {
+        line_counts = args.get(i + 1)
+            .map(|arg| arg.split(',').filter_map(|n| n.trim().parse().ok()).filter(|&n| n > 0).collect())
+            .unwrap_or(Vec::new());
+        if line_counts.is_empty() {
+            println!("Invalid line counts, expected --lines n1,n2,...");
+            return;
+        }
+        args.drain(i..i + 2);
+    }
+
+    let mut binarisation = Binarisation::default();
+    if let Some(i) = args.iter().position(|arg| arg == "--threshold") {
+        match args.get(i + 1).and_then(|arg| Binarisation::from_name(arg)) {
+            Some(method) => binarisation = method,
+            None => {
+                println!("Invalid threshold, expected one of: fixed, otsu, sauvola, niblack, local_mean");
+                return;
+            },
+        }
+        args.drain(i..i + 2);
+    }
+
+    let mut region = None;
+    if let Some(i) = args.iter().position(|arg| arg == "--region") {
+        region = args.get(i + 1).and_then(|arg| parse_region(arg));
+        if region.is_none() {
+            println!("Invalid region, expected --region x,y,width,height");
+            return;
+        }
+        args.drain(i..i + 2);
+    }
+
+    // Fit curved staves (e.g. on photos of bound books) instead of straight ones:
+    let mut curved = false;
+    if let Some(i) = args.iter().position(|arg| arg == "--curved") {
+        curved = true;
+        args.remove(i);
+    }
+
+    let file_names = args;
+    if file_names.is_empty() {
+        println!("Usage: staff_detect_image [--region x,y,width,height] [--threshold method] [--lines n1,n2,...] [--curved] <image file>...");
+        return;
+    }
+
+    for file_name in &file_names {
+        let image = match image_file::load_image_nv12(file_name) {
+            Ok(image) => image,
+            Err(err) => {
+                println!("{}: Failed to load image: {}", file_name, err);
+                continue;
+            },
+        };
+
+        // Detection only requires the luma plane:
+        let grey_frame = image.luma_image();
+
+        // Estimate staff line and space widths over the whole page, to reject implausible crosses:
+        let binarizer = binarisation.build(&grey_frame);
+        let (_, staff_metrics) = omr::detection::runs::histogram::staff_metrics(&grey_frame, &*binarizer);
+        println!("{}: line width {} (confidence {:.2}), space width {} (confidence {:.2}).",
+            file_name,
+            staff_metrics.line_width.value, staff_metrics.line_width.confidence,
+            staff_metrics.space_width.value, staff_metrics.space_width.confidence);
+
+        let scan_params = ScanParams {
+            line_counts: line_counts.clone(),
+            staff_metrics: Some(staff_metrics),
+            .. Default::default()
+        };
+        let cross_points = match region {
+            Some(region) => {
+                match omr::detection::scanning::staff_cross::scan_region(&grey_frame, region, &binarisation, &scan_params) {
+                    Ok(crosses) => crosses,
+                    Err(err) => {
+                        println!("{}: Invalid region: {}", file_name, err);
+                        continue;
+                    },
+                }
+            },
+            None => {
+                // Scan at multiple resolutions, so that both small and large staves are found:
+                let pyramid = ImagePyramid::new(&grey_frame, 3, 2.0, 64);
+                omr::detection::scanning::staff_cross::scan_image_pyramid(&pyramid, &binarisation, &scan_params)
+            },
+        };
+
+        let sample_params = SampleParams {
+            binarizer: Some(&*binarizer),
+            .. Default::default()
+        };
+
+        let params = omr::detection::ransac::RansacParams {
+            num_iterations: 0, // set by ransac_multiple
+            min_inliers: 15,
+            sampling: Default::default(),
+            scoring: Default::default(),
+            local_optimisation_iterations: 5,
+            thread_pool: Default::default(),
+        };
+        // Note: A fixed seed makes detections reproducible.
+        let multi_params = omr::detection::ransac::MultiRansacParams::default();
+        let mut rng = rand::XorShiftRng::new_unseeded();
+        let staff_model = StaffCrossLineModel {
+            staff_metrics: Some(staff_metrics),
+            .. Default::default()
+        };
+
+        if curved {
+            let curve_model = StaffCurveModel {
+                num_segments: 3,
+                thresholds: staff_model,
+                .. Default::default()
+            };
+            let states = omr::detection::ransac::ransac_multiple(
+                &curve_model, &params, &multi_params, &cross_points, &mut rng);
+
+            println!("{}: {}x{}, {} staff crosses, {} candidate curved staves.",
+                file_name, image.width(), image.height(), cross_points.len(), states.len());
+
+            for state in &states {
+                if let Some(ref staff) = state.model {
+                    print_staff_segments(&grey_frame, staff, &sample_params);
+                }
+            }
+            continue;
+        }
+
+        let states = omr::detection::ransac::ransac_multiple(
+            &staff_model, &params, &multi_params, &cross_points, &mut rng);
+
+        println!("{}: {}x{}, {} staff crosses, {} candidate staves.",
+            file_name, image.width(), image.height(), cross_points.len(), states.len());
+
+        for state in &states {
+            // Note: The model has been refined to fit all of its inliers.
+            let staff = match state.model {
+                Some(ref model) => model.to_staff(),
+                None => continue,
+            };
+
+            print_staff_segments(&grey_frame, &staff, &sample_params);
+        }
+    }
+}
@@ -5,14 +5,98 @@ use optical_music_recognition::ffmpeg_camera::ffmpeg_camera;
 // use optical_music_recognition::ffmpeg_camera::image_ycbcr;
 use optical_music_recognition::ffmpeg_camera::image::Image;
 
+use std::env;
+
+// Parses a capture mode of the form "WIDTHxHEIGHT@FPS" (e.g. "1280x720@30").
+fn parse_mode(mode_str : &str) -> Option<((usize, usize), f64)> {
+    let mut parts = mode_str.split('@');
+    let size_str = parts.next().unwrap_or("");
+    let framerate = match parts.next().and_then(|r| r.trim().parse().ok()) {
+        Some(framerate) => framerate,
+        None => return None,
+    };
+
+    let sizes : Vec<usize> = size_str.split('x').filter_map(|v| v.trim().parse().ok()).collect();
+    if sizes.len() != 2 || parts.next().is_some() {
+        return None;
+    }
+
+    Some(((sizes[0], sizes[1]), framerate))
+}
+
+// Usage:
+//     webcam_photo                 Take a photo with the default camera.
+//     webcam_photo --list          List the available cameras and their modes.
+//     webcam_photo <device name> [--mode WxH@fps]
+//                                  Take a photo with the named camera, in the given mode. By
+//                                  default, the largest frame size available at about 30fps is
+//                                  used.
 fn main() {
+    let mut args : Vec<String> = env::args().skip(1).collect();
+
+    let mut mode = None;
+    if let Some(i) = args.iter().position(|arg| arg == "--mode") {
+        mode = args.get(i + 1).and_then(|arg| parse_mode(arg));
+        if mode.is_none() {
+            println!("Invalid mode, expected --mode WxH@fps (e.g. --mode 1280x720@30)");
+            return;
+        }
+        args.drain(i..i + 2);
+    }
+
+    let arg = args.get(0).cloned();
+    if mode.is_some() && arg.is_none() {
+        println!("A device name is required with --mode. Run with --list to see the available devices.");
+        return;
+    }
+
+    let devices = if arg.is_some() {
+        ffmpeg_camera::FfmpegCamera::list_devices()
+            .expect("Failed to list devices.")
+    } else {
+        Vec::new()
+    };
+
+    if arg.as_ref().map_or(false, |a| a == "--list") {
+        for device in &devices {
+            println!("{}", device.name);
+            println!("    pixel formats: {}", device.pixel_formats.join(", "));
+            for mode in &device.modes {
+                let framerates : Vec<String> = mode.framerates.iter().map(|r| format!("{:.6}", r)).collect();
+                println!("    {}x{} @ [{}] fps", mode.video_size.0, mode.video_size.1, framerates.join(", "));
+            }
+        }
+        return;
+    }
+
+    let mut camera = match arg {
+        Some(device_name) => {
+            let device = devices.iter().find(|d| d.name == device_name)
+                .expect("Device not found. Run with --list to see the available devices.");
+            let spec = match mode {
+                Some((video_size, framerate)) => {
+                    let mode = device.find_mode(video_size)
+                        .expect("Mode not supported. Run with --list to see the available modes.");
+                    device.camera_spec_at(mode, framerate)
+                },
+                None => device.preferred_camera_spec()
+                    .expect("Device reported no capture modes."),
+            };
+            println!("Using {}x{} @ {} fps.", spec.video_size.0, spec.video_size.1, spec.framerate);
 
-    let mut camera =
-        ffmpeg_camera::FfmpegCamera::get_default()
-            .expect("Failed to open camera.");
+            ffmpeg_camera::FfmpegCamera::get_camera(&spec)
+                .expect("Failed to open camera.")
+        },
+        None => {
+            ffmpeg_camera::FfmpegCamera::get_default()
+                .expect("Failed to open camera.")
+        },
+    };
 
     let image = camera.get_image_uyvy().unwrap();
 
     image.save_pgm("image.pgm").unwrap();
+    image.save_ppm("image.ppm").unwrap();
+    image.save_png("image.png").unwrap();
     image.save_jpeg("image.jpg").unwrap();
 }
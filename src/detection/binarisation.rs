@@ -0,0 +1,303 @@
+use ffmpeg_camera::image::Image;
+use nalgebra as na;
+use std::cmp;
+use std::fmt;
+
+// Classifies image pixels as black (ink) or white (paper).
+// Note: Binarizers are shared between scanning threads, so must be Sync.
+pub trait Binarizer : fmt::Debug + Sync {
+    // Returns the luma threshold at a pixel. Darker pixels are black, and brighter pixels are white.
+    // Note: Pixels outside the image use the threshold of the nearest pixel in the image.
+    fn threshold(&self, col: usize, row: usize) -> f32;
+
+    fn is_black(&self, col: usize, row: usize, luma: u8) -> bool {
+        (luma as f32) < self.threshold(col, row)
+    }
+
+    fn is_white(&self, col: usize, row: usize, luma: u8) -> bool {
+        (luma as f32) > self.threshold(col, row)
+    }
+
+    // Returns the threshold at the pixel containing an image point.
+    fn threshold_at(&self, pt: na::Vector2<f32>) -> f32 {
+        self.threshold(pt[0].max(0.0) as usize, pt[1].max(0.0) as usize)
+    }
+}
+
+// Selects a binarisation method, so that it can be configured per input and applied to each image
+// (or pyramid level) that is scanned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binarisation {
+    // A fixed luma threshold.
+    Fixed(u8),
+    // A single threshold chosen from the image's histogram.
+    Otsu,
+    // Local thresholds from the mean and standard deviation of a (2 * radius + 1) square window.
+    Sauvola { radius: usize, k: f32, r: f32 },
+    Niblack { radius: usize, k: f32, min_stddev: f32 },
+    // Local thresholds a fixed offset below the mean of a (2 * radius + 1) square window.
+    LocalMean { radius: usize, offset: f32 },
+}
+
+impl Default for Binarisation {
+    fn default() -> Binarisation {
+        Binarisation::Fixed(128)
+    }
+}
+
+impl Binarisation {
+    // Default parameters for each method, by name (e.g. from the command line).
+    pub fn from_name(name: &str) -> Option<Binarisation> {
+        match name {
+            "fixed" => Some(Binarisation::Fixed(128)),
+            "otsu" => Some(Binarisation::Otsu),
+            "sauvola" => Some(Binarisation::Sauvola { radius: 7, k: 0.34, r: 128.0 }),
+            // Note: These parameters match the ADAPTIVE_THRESHOLD shader.
+            "niblack" => Some(Binarisation::Niblack { radius: 5, k: -0.1, min_stddev: 2.55 }),
+            "local_mean" => Some(Binarisation::LocalMean { radius: 7, offset: 10.0 }),
+            _ => None,
+        }
+    }
+
+    pub fn build<I: Image>(&self, image: &I) -> Box<Binarizer> {
+        match *self {
+            Binarisation::Fixed(threshold) => Box::new(FixedThreshold::new(threshold)),
+            Binarisation::Otsu => Box::new(FixedThreshold::otsu(image)),
+            Binarisation::Sauvola { radius, k, r } => Box::new(SauvolaThreshold::new(image, radius, k, r)),
+            Binarisation::Niblack { radius, k, min_stddev } => Box::new(NiblackThreshold::new(image, radius, k, min_stddev)),
+            Binarisation::LocalMean { radius, offset } => Box::new(LocalMeanThreshold::new(image, radius, offset)),
+        }
+    }
+}
+
+// The same threshold for every pixel.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedThreshold {
+    pub threshold: f32,
+}
+
+impl FixedThreshold {
+    pub fn new(threshold: u8) -> FixedThreshold {
+        FixedThreshold {
+            threshold: threshold as f32,
+        }
+    }
+
+    // Chooses the threshold that minimises the intra-class variance of the image's black and white
+    // pixels (Otsu's method).
+    pub fn otsu<I: Image>(image: &I) -> FixedThreshold {
+        let mut histogram = [0usize; 256];
+        for row in 0..image.height() {
+            for col in 0..image.width() {
+                histogram[image.luma(col, row) as usize] += 1;
+            }
+        }
+
+        let total = (image.width() * image.height()) as f64;
+        let total_sum = histogram.iter().enumerate().fold(0.0, |sum, (i, &n)| sum + (i * n) as f64);
+
+        let mut best_threshold = 128;
+        let mut best_variance = 0.0;
+        let mut black_count = 0.0;
+        let mut black_sum = 0.0;
+        for t in 0..256 {
+            // Pixels below t are black:
+            let white_count = total - black_count;
+            if black_count > 0.0 && white_count > 0.0 {
+                let black_mean = black_sum / black_count;
+                let white_mean = (total_sum - black_sum) / white_count;
+                let variance = black_count * white_count * (black_mean - white_mean).powi(2);
+                if variance > best_variance {
+                    best_variance = variance;
+                    best_threshold = t;
+                }
+            }
+
+            black_count += histogram[t] as f64;
+            black_sum += (t * histogram[t]) as f64;
+        }
+
+        FixedThreshold {
+            threshold: best_threshold as f32,
+        }
+    }
+}
+
+impl Binarizer for FixedThreshold {
+    fn threshold(&self, _: usize, _: usize) -> f32 {
+        self.threshold
+    }
+}
+
+// Summed area tables of an image's luma and squared luma, for computing window statistics in
+// constant time.
+#[derive(Clone)]
+pub struct IntegralImage {
+    pub width: usize,
+    pub height: usize,
+
+    // (width + 1) x (height + 1) tables, where entry (c, r) is the sum over pixels [0, c) x [0, r).
+    sums: Vec<f64>,
+    sq_sums: Vec<f64>,
+}
+
+impl fmt::Debug for IntegralImage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "IntegralImage {{ width: {}, height: {} }}", self.width, self.height)
+    }
+}
+
+impl IntegralImage {
+    // Note: Empty images are allowed (e.g. a region clipped to nothing), and have no statistics
+    // (see window_stats).
+    pub fn new<I: Image>(image: &I) -> IntegralImage {
+        let width = image.width();
+        let height = image.height();
+        if width == 0 || height == 0 {
+            return IntegralImage {
+                width: 0,
+                height: 0,
+                sums: vec![0.0],
+                sq_sums: vec![0.0],
+            };
+        }
+
+        let stride = width + 1;
+
+        let mut sums = vec![0.0; stride * (height + 1)];
+        let mut sq_sums = vec![0.0; stride * (height + 1)];
+        for row in 0..height {
+            let mut row_sum = 0.0;
+            let mut row_sq_sum = 0.0;
+            for col in 0..width {
+                let luma = image.luma(col, row) as f64;
+                row_sum += luma;
+                row_sq_sum += luma * luma;
+
+                let i = (row + 1) * stride + col + 1;
+                sums[i] = sums[i - stride] + row_sum;
+                sq_sums[i] = sq_sums[i - stride] + row_sq_sum;
+            }
+        }
+
+        IntegralImage {
+            width: width,
+            height: height,
+            sums: sums,
+            sq_sums: sq_sums,
+        }
+    }
+
+    // Returns the mean and standard deviation of the luma in the square window of the given radius
+    // around a pixel (clipped to the image).
+    // Returns zeros for an empty image.
+    pub fn window_stats(&self, col: usize, row: usize, radius: usize) -> (f32, f32) {
+        if self.width == 0 || self.height == 0 {
+            return (0.0, 0.0);
+        }
+
+        let col = cmp::min(col, self.width - 1);
+        let row = cmp::min(row, self.height - 1);
+
+        let c0 = col.saturating_sub(radius);
+        let r0 = row.saturating_sub(radius);
+        let c1 = cmp::min(self.width, col + radius + 1);
+        let r1 = cmp::min(self.height, row + radius + 1);
+
+        let stride = self.width + 1;
+        let area_sum = |table: &Vec<f64>| {
+            table[r1 * stride + c1] - table[r0 * stride + c1] - table[r1 * stride + c0] + table[r0 * stride + c0]
+        };
+
+        let n = ((c1 - c0) * (r1 - r0)) as f64;
+        let mean = area_sum(&self.sums) / n;
+        let variance = (area_sum(&self.sq_sums) / n - mean * mean).max(0.0);
+
+        (mean as f32, variance.sqrt() as f32)
+    }
+}
+
+// Sauvola's method: T = m * (1 + k * (s / r - 1)), where r is the dynamic range of the standard
+// deviation. Works well for unevenly lit pages.
+#[derive(Debug, Clone)]
+pub struct SauvolaThreshold {
+    pub integral: IntegralImage,
+    pub radius: usize,
+    pub k: f32,
+    pub r: f32,
+}
+
+impl SauvolaThreshold {
+    pub fn new<I: Image>(image: &I, radius: usize, k: f32, r: f32) -> SauvolaThreshold {
+        SauvolaThreshold {
+            integral: IntegralImage::new(image),
+            radius: radius,
+            k: k,
+            r: r,
+        }
+    }
+}
+
+impl Binarizer for SauvolaThreshold {
+    fn threshold(&self, col: usize, row: usize) -> f32 {
+        let (mean, stddev) = self.integral.window_stats(col, row, self.radius);
+        mean * (1.0 + self.k * (stddev / self.r - 1.0))
+    }
+}
+
+// Niblack's method: T = m + k * s.
+// Windows with a standard deviation below min_stddev are treated as blank paper.
+#[derive(Debug, Clone)]
+pub struct NiblackThreshold {
+    pub integral: IntegralImage,
+    pub radius: usize,
+    pub k: f32,
+    pub min_stddev: f32,
+}
+
+impl NiblackThreshold {
+    pub fn new<I: Image>(image: &I, radius: usize, k: f32, min_stddev: f32) -> NiblackThreshold {
+        NiblackThreshold {
+            integral: IntegralImage::new(image),
+            radius: radius,
+            k: k,
+            min_stddev: min_stddev,
+        }
+    }
+}
+
+impl Binarizer for NiblackThreshold {
+    fn threshold(&self, col: usize, row: usize) -> f32 {
+        let (mean, stddev) = self.integral.window_stats(col, row, self.radius);
+        if stddev < self.min_stddev {
+            return 0.0;
+        }
+
+        mean + self.k * stddev
+    }
+}
+
+// T = m - offset.
+#[derive(Debug, Clone)]
+pub struct LocalMeanThreshold {
+    pub integral: IntegralImage,
+    pub radius: usize,
+    pub offset: f32,
+}
+
+impl LocalMeanThreshold {
+    pub fn new<I: Image>(image: &I, radius: usize, offset: f32) -> LocalMeanThreshold {
+        LocalMeanThreshold {
+            integral: IntegralImage::new(image),
+            radius: radius,
+            offset: offset,
+        }
+    }
+}
+
+impl Binarizer for LocalMeanThreshold {
+    fn threshold(&self, col: usize, row: usize) -> f32 {
+        let (mean, _) = self.integral.window_stats(col, row, self.radius);
+        mean - self.offset
+    }
+}
@@ -1,3 +1,5 @@
 pub mod scanning;
 pub mod ransac;
 pub mod refinement;
+pub mod binarisation;
+pub mod runs;
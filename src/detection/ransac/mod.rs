@@ -1,11 +1,13 @@
 pub mod staff_cross;
+pub mod staff_curve;
 
 use rand;
 // use rand::SeedableRng;
 use rand::Rng;
-use rand::distributions::IndependentSample;
+// use rand::distributions::IndependentSample;
 // use rand::distributions::Range;
 use std;
+use utility::thread_pool::ThreadPool;
 
 #[inline(never)]
 pub fn choose(n: usize, k: usize) -> usize {
@@ -64,100 +66,357 @@ pub fn calculate_num_iterations(
     // Probability of choosing correctly in a single iteration:
     let f = k_m as f64 / n_m as f64;
 
+    // Handle the limits, where the formula below is undefined:
+    if p <= 0.0 {
+        return 0;
+    }
+    if f >= 1.0 {
+        return 1;
+    }
+    if p >= 1.0 || f <= 0.0 {
+        // Note: Callers should cap the number of iterations (see MultiRansacParams).
+        return usize::max_value();
+    }
+
     // Iterations required to have probability p of choosing correctly at least once:
-    (1.0 - p).log(1.0 - f) as usize
+    let iterations = (1.0 - p).log(1.0 - f);
+    if !(iterations < usize::max_value() as f64) {
+        return usize::max_value();
+    }
+    iterations as usize
+}
+
+// Returns k distinct indices in [0, n), chosen uniformly at random.
+// Uses Floyd's algorithm, which makes exactly k random draws.
+// Note: Using rand::sample is *much* slower than sampling indices directly.
+#[inline(never)]
+pub fn sample_indices<R: Rng>(rng: &mut R, n: usize, k: usize) -> Vec<usize> {
+    if k > n {
+        panic!("sample_indices: Cannot sample more indices than there are points!")
+    }
+
+    let mut indices = Vec::with_capacity(k);
+    for j in (n - k)..n {
+        let t = rng.gen_range(0, j + 1);
+        if indices.contains(&t) {
+            indices.push(j);
+        } else {
+            indices.push(t);
+        }
+    }
+
+    indices
 }
 
 
 // Fit stafflines using RANSAC:
 
+// Note: Implementations may carry their own configuration (e.g. inlier thresholds), and are shared
+// between the threads that score hypotheses.
 pub trait RansacModel<Model, Point> {
     // Find the best model that best fits a minimal set of points
-    fn fit_inliers(&[&Point]) -> Model;
+    // Returns None if the points cannot be fitted, in which case the hypothesis is skipped
+    fn fit_inliers(&self, &[&Point]) -> Option<Model>;
 
     // Number of points required for fitInliers
     // Note: this is a method due to the following:
     //      error: associated constants are experimental (see issue #29646)
-    fn num_required() -> usize;
+    fn num_required(&self) -> usize;
+
+    // Whether a minimal set of points cannot determine a unique model (e.g. coincident points)
+    // Degenerate samples are skipped
+    fn is_degenerate(&self, _: &[&Point]) -> bool {
+        false
+    }
 
-    // Find the number of points within a given threshold of the model
-    fn find_inliers(f32, &Vec<Point>, &Model) -> Vec<Point>;
+    // Find the points that are within the model's inlier thresholds
+    // Note: Thresholds are configured on the implementation (e.g. StaffCrossLineModel).
+    fn find_inliers(&self, &Vec<Point>, &Model) -> Vec<Point>;
 
-    // Return all points that are not within a given threshold of the model
-    fn find_outliers(f32, &Vec<Point>, &Model) -> Vec<Point>;
+    // Return all points that are not inliers of the model
+    fn find_outliers(&self, &Vec<Point>, &Model) -> Vec<Point>;
 
     // Find the model that best fits a large set of points
-    fn fit_model(&Vec<Point>) -> Option<Model>;
+    fn fit_model(&self, &Vec<Point>) -> Option<Model>;
+
+    // The weight of a point's support for a model (e.g. its confidence)
+    // Hypotheses are ranked by the total weight of their inliers, and PROSAC samples the heaviest
+    // points first
+    fn point_weight(&self, _: &Point) -> f32 {
+        1.0
+    }
+
+    // The distance from a point to the model, scaled so that points at 1.0 are on the inlier
+    // threshold (used by MSAC and MLESAC scoring)
+    // Points that cannot belong to the model should return infinity
+    fn residual(&self, &Model, &Point) -> f32;
   }
 
-#[derive(Clone, Copy)]
+// How hypotheses are scored. Higher scores are better.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RansacScoring {
+    // The total weight of the inliers.
+    InlierCount,
+
+    // MSAC: Each inlier scores its weight, reduced by its squared residual (i.e. a truncated
+    // quadratic cost).
+    Msac,
+
+    // MLESAC: The log likelihood of the data, under a mixture of Gaussian inliers with standard
+    // deviation sigma and uniformly distributed outliers over outlier_range (both in residual
+    // units). The inlier fraction is estimated by EM for each hypothesis.
+    Mlesac { sigma: f32, outlier_range: f32 },
+}
+
+impl Default for RansacScoring {
+    fn default() -> RansacScoring {
+        RansacScoring::InlierCount
+    }
+}
+
+// How points are chosen for each hypothesis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RansacSampling {
+    // Uniformly from all points.
+    Uniform,
+
+    // PROSAC: From progressively larger sets of the highest weighted points (see point_weight).
+    Prosac,
+}
+
+impl Default for RansacSampling {
+    fn default() -> RansacSampling {
+        RansacSampling::Uniform
+    }
+}
+
+#[derive(Clone)]
 pub struct RansacParams {
     // Number of attempted model fits
     pub num_iterations : usize,
 
-    // Minimum distance to model to count as an inlier
-    pub max_distance : f32,
-
     // Minimum number of inliers required for a model to be accepted
     pub min_inliers : usize,
+
+    // How hypotheses are sampled and scored
+    pub sampling : RansacSampling,
+    pub scoring : RansacScoring,
+
+    // Maximum number of times to refit the best model to its inliers (see RansacModel::fit_model)
+    // Refitting stops early once the inliers stop changing, or if it would make the model worse
+    pub local_optimisation_iterations : usize,
+
+    // Hypotheses are scored in parallel with this
+    pub thread_pool : ThreadPool,
 }
 
 pub struct RansacState<Model, Point> {
     // samples : Vec<Point>,
     pub model : Option<Model>,
     pub inliers : Vec<Point>,
+
+    // Score of the model (see RansacScoring)
+    pub score : f32,
 }
 
+// Returns the sampled indices for each PROSAC iteration (Chum & Matas, 2005).
+// Samples are drawn from the first n points in order of decreasing weight, where n grows from the
+// minimal sample size to all of the points, reaching all points after num_iterations.
 #[inline(never)]
-pub fn ransac<RM, Model, Point, R: Rng>(params: &RansacParams, data: &Vec<Point>, rng: &mut R)
+pub fn prosac_samples<R: Rng>(rng: &mut R, weights: &[f32], k: usize, num_iterations: usize) -> Vec<Vec<usize>> {
+    let num_points = weights.len();
+
+    // Note: The sort is stable, so equal weights keep their order.
+    let mut order : Vec<usize> = (0..num_points).collect();
+    order.sort_by(|&a, &b| weights[b].partial_cmp(&weights[a]).unwrap_or(std::cmp::Ordering::Equal));
+
+    // Expected number of samples drawn from the first n points, in uniform sampling:
+    let mut t_n = num_iterations as f64;
+    for i in 0..k {
+        t_n *= (k - i) as f64 / (num_points - i) as f64;
+    }
+    let mut t_n_prime = 1;
+    let mut n = k;
+
+    let mut samples = Vec::with_capacity(num_iterations);
+    for t in 1..(num_iterations + 1) {
+        if t > t_n_prime && n < num_points {
+            let t_n_next = t_n * (n + 1) as f64 / (n + 1 - k) as f64;
+            n += 1;
+            t_n_prime += (t_n_next - t_n).ceil() as usize;
+            t_n = t_n_next;
+        }
+
+        let indices = if t_n_prime < t || k == 0 {
+            // The set has been fully used, so sample from it uniformly:
+            sample_indices(rng, n, k)
+        } else {
+            // Always include the newest point in the set:
+            let mut indices = sample_indices(rng, n - 1, k - 1);
+            indices.push(n - 1);
+            indices
+        };
+
+        samples.push(indices.iter().map(|&i| order[i]).collect());
+    }
+
+    samples
+}
+
+// Scores a hypothesis (see RansacScoring).
+fn score_model<RM, Model, Point>(ransac_model: &RM, scoring: RansacScoring, data: &Vec<Point>, model: &Model, inliers: &[Point]) -> f32
+    where RM: RansacModel<Model, Point> {
+
+    match scoring {
+        RansacScoring::InlierCount => {
+            inliers.iter().fold(0.0, |sum, pt| sum + ransac_model.point_weight(pt))
+        },
+        RansacScoring::Msac => {
+            inliers.iter().fold(0.0, |sum, pt| {
+                let residual = ransac_model.residual(model, pt).min(1.0);
+                sum + ransac_model.point_weight(pt) * (1.0 - residual * residual)
+            })
+        },
+        RansacScoring::Mlesac { sigma, outlier_range } => {
+            let residuals : Vec<f32> = data.iter().map(|pt| ransac_model.residual(model, pt)).collect();
+            let norm = 1.0 / ((2.0 * std::f32::consts::PI).sqrt() * sigma);
+            let inlier_likelihood = |r: f32| norm * (-r * r / (2.0 * sigma * sigma)).exp();
+            let outlier_likelihood = 1.0 / outlier_range;
+
+            // Estimate the inlier fraction by EM:
+            let mut gamma = 0.5;
+            for _ in 0..5 {
+                let sum = residuals.iter().fold(0.0, |sum, &r| {
+                    let p_in = gamma * inlier_likelihood(r);
+                    let p_out = (1.0 - gamma) * outlier_likelihood;
+                    sum + p_in / (p_in + p_out)
+                });
+                gamma = sum / residuals.len() as f32;
+            }
+
+            data.iter().zip(residuals.iter()).fold(0.0, |sum, (pt, &r)| {
+                let likelihood = gamma * inlier_likelihood(r) + (1.0 - gamma) * outlier_likelihood;
+                sum + ransac_model.point_weight(pt) * likelihood.ln()
+            })
+        },
+    }
+}
+
+#[inline(never)]
+pub fn ransac<RM, Model, Point, R: Rng>(ransac_model: &RM, params: &RansacParams, data: &Vec<Point>, rng: &mut R)
     -> RansacState<Model, Point>
     // -> Option<Model>
-    where RM: RansacModel<Model, Point>
-        , Point: Clone {
+    where RM: RansacModel<Model, Point> + Sync
+        , Model: Send
+        , Point: Clone + Send + Sync {
 
-    let mut best_state = RansacState::<Model, Point> {
+    let empty_state = || RansacState::<Model, Point> {
         // samples: Vec::new(),
         model: None,
         inliers: Vec::new(),
+        score: std::f32::NEG_INFINITY,
     };
 
     // If there are too few points, just return None:
-    if data.len() < RM::num_required() {
+    if data.len() < ransac_model.num_required() {
         // return None;
+        return empty_state();
+    }
+
+    // Randomly select points for every hypothesis up front, so that the random sequence (and
+    // therefore the result) does not depend on how hypotheses are scheduled:
+    let k = ransac_model.num_required();
+    let samples : Vec<Vec<usize>> = match params.sampling {
+        RansacSampling::Uniform => {
+            (0..params.num_iterations).map(|_| sample_indices(rng, data.len(), k)).collect()
+        },
+        RansacSampling::Prosac => {
+            let weights : Vec<f32> = data.iter().map(|pt| ransac_model.point_weight(pt)).collect();
+            prosac_samples(rng, &weights, k, params.num_iterations)
+        },
+    };
+
+    let hypotheses : Vec<Vec<usize>> = samples.into_iter().filter(|indices| {
+        let points : Vec<&Point> = indices.iter().map(|&i| &data[i]).collect();
+        !ransac_model.is_degenerate(&points)
+    }).collect();
+
+    // Find the best hypothesis in each chunk:
+    let chunk_states = params.thread_pool.map_chunks(&hypotheses, |chunk| {
+        let mut best_state = empty_state();
+
+        for indices in chunk {
+            let samples : Vec<&Point> = indices.iter().map(|&i| &data[i]).collect();
+
+            // Fit the model:
+            // println!("Fit the model:");
+            let current_fit = match ransac_model.fit_inliers(&samples) {
+                Some(model) => model,
+                None => continue,
+            };
+
+            // Find the set of inliers:
+            // println!("Find the set of inliers:");
+            let current_inliers = ransac_model.find_inliers(&data, &current_fit);
+
+            let current_score = score_model(ransac_model, params.scoring, &data, &current_fit, &current_inliers);
+
+            // If the current fit is better than the current best fit:
+            // println!("If the current fit is better than the current best fit:");
+            // Note: Models must explain at least one point, as any model beats the empty state's
+            // score.
+            if current_score > best_state.score {
+                if !current_inliers.is_empty() && current_inliers.len() >= params.min_inliers {
+                    // Replace the best model:
+                    best_state = RansacState {
+                        // samples: samples,
+                        model: Some(current_fit),
+                        inliers: current_inliers,
+                        score: current_score,
+                    };
+                }
+            }
+        }
+
+        best_state
+    });
+
+    // Chunks are in hypothesis order, and earlier hypotheses win ties, as if they were scored in
+    // sequence:
+    let mut best_state = empty_state();
+    for state in chunk_states {
+        if state.score > best_state.score {
+            best_state = state;
+        }
+    }
+
+    if best_state.model.is_none() {
         return best_state;
     }
 
-    let between = rand::distributions::Range::new(0, data.len());
-    for _ in 0..params.num_iterations {
-        // Randomly select points:
-        // Note: Using rand::sample is *much* slower than just sampling two random indices.
-        // let samples = rand::sample(rng, data, RM::num_required());
-        let a = between.ind_sample(rng);
-        let b = between.ind_sample(rng);
-        let samples = [&data[a], &data[b]];
-        if a == b {
-            continue;
+    // Local optimisation: Refit the model to its inliers, and repeat with the new inliers:
+    for _ in 0..params.local_optimisation_iterations {
+        let refined_fit = match ransac_model.fit_model(&best_state.inliers) {
+            Some(model) => model,
+            None => break,
+        };
+
+        let refined_inliers = ransac_model.find_inliers(&data, &refined_fit);
+        let refined_score = score_model(ransac_model, params.scoring, &data, &refined_fit, &refined_inliers);
+        if refined_inliers.is_empty() || refined_inliers.len() < params.min_inliers || refined_score < best_state.score {
+            break;
         }
 
-        // Fit the model:
-        // println!("Fit the model:");
-        let current_fit = RM::fit_inliers(&samples);
-
-        // Find the set of inliers:
-        // println!("Find the set of inliers:");
-        let current_inliers = RM::find_inliers(params.max_distance, &data, &current_fit);
-
-        // If the current fit is better than the current best fit:
-        // println!("If the current fit is better than the current best fit:");
-        if current_inliers.len() > best_state.inliers.len() {
-            if current_inliers.len() >= params.min_inliers {
-                // Replace the best model:
-                best_state = RansacState {
-                    // samples: samples,
-                    model: Some(current_fit),
-                    inliers: current_inliers,
-                };
-            }
+        let is_stable = refined_inliers.len() == best_state.inliers.len() && refined_score == best_state.score;
+
+        best_state = RansacState {
+            model: Some(refined_fit),
+            inliers: refined_inliers,
+            score: refined_score,
+        };
+
+        if is_stable {
+            break;
         }
     }
 
@@ -165,42 +424,89 @@ pub fn ransac<RM, Model, Point, R: Rng>(params: &RansacParams, data: &Vec<Point>
     best_state
 }
 
+// Controls how ransac_multiple finds successive models.
+#[derive(Clone, Copy, Debug)]
+pub struct MultiRansacParams {
+    // The required probability of finding each model
+    pub success_probability : f32,
+
+    // The expected fraction of all points that belong to each model
+    // Note: Used (with the number of remaining points) to choose the number of iterations.
+    pub inlier_ratio : f32,
+
+    // Stop after finding this many models
+    pub max_models : usize,
+
+    // Stop when fewer than this many points remain unexplained
+    pub min_remaining_points : usize,
+
+    // The most iterations to run when searching for each model
+    // Note: The number of iterations grows without bound as success_probability approaches 1.
+    pub max_iterations : usize,
+}
+
+impl Default for MultiRansacParams {
+    fn default() -> MultiRansacParams {
+        MultiRansacParams {
+            success_probability: 0.75,
+            inlier_ratio: 0.05,
+            max_models: usize::max_value(),
+            min_remaining_points: 0,
+            max_iterations: 10000,
+        }
+    }
+}
+
+// Repeatedly finds the best model, and removes its inliers from the data, until no acceptable model
+// is found (or a limit in multi_params is reached).
 #[inline(never)]
-pub fn ransac_multiple<RM, Model, Point>(params: &RansacParams, data: &Vec<Point>)
+pub fn ransac_multiple<RM, Model, Point, R: Rng>(
+    ransac_model: &RM,
+    params: &RansacParams,
+    multi_params: &MultiRansacParams,
+    data: &Vec<Point>,
+    rng: &mut R)
     -> Vec<RansacState<Model, Point>>
     // -> Option<Model>
-    where RM: RansacModel<Model, Point>
-        , Point: Clone {
-
-    // let mut thread_rng = rand::thread_rng();
-    // let mut rng = rand::XorShiftRng::from_seed(thread_rng.gen::<[u32; 4]>());
-    let mut rng = rand::XorShiftRng::new_unseeded();
+    where RM: RansacModel<Model, Point> + Sync
+        , Model: Send
+        , Point: Clone + Send + Sync {
 
     let mut states = Vec::new();
     let mut new_data = data.clone();
 
-    loop {
+    // Probabilities and ratios outside [0, 1] are meaningless, so are clamped:
+    let success_probability = multi_params.success_probability.max(0.0).min(1.0);
+    let inlier_ratio = multi_params.inlier_ratio.max(0.0).min(1.0);
+    let expected_inliers = (data.len() as f32 * inlier_ratio) as usize;
+
+    while states.len() < multi_params.max_models && new_data.len() >= multi_params.min_remaining_points {
         let num_iterations = calculate_num_iterations(
             new_data.len(), // num_points
-            std::cmp::min(new_data.len() / 2, data.len() / 20), // num_inliers
-            RM::num_required(), // points_per_model
-            0.75 // success_probability
+            std::cmp::min(new_data.len() / 2, expected_inliers), // num_inliers
+            ransac_model.num_required(), // points_per_model
+            success_probability // success_probability
         );
+        let num_iterations = std::cmp::min(num_iterations, multi_params.max_iterations);
         let mut new_params = params.clone();
         new_params.num_iterations = num_iterations;
         // println!("num_iterations: {:?}", num_iterations);
 
-        let state = ransac::<RM, Model, Point, _>(&new_params, &new_data, &mut rng);
+        let state = ransac(ransac_model, &new_params, &new_data, rng);
         // let state = ransac::<RM, Model, Point>(params, &new_data);
 
-        if state.model.is_some() {
-            if let Some(ref model) = state.model {
-                new_data = RM::find_outliers(params.max_distance, &new_data, &model);
-            }
-            states.push(state);
-        } else {
+        let outliers = match state.model {
+            Some(ref model) => ransac_model.find_outliers(&new_data, &model),
+            None => break,
+        };
+
+        // Stop if the model explains none of the remaining points, as it would be found again:
+        if outliers.len() >= new_data.len() {
             break;
         }
+
+        new_data = outliers;
+        states.push(state);
     }
 
     states
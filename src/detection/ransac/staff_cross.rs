@@ -1,14 +1,21 @@
 
 use detection::ransac::RansacModel;
 use detection::scanning::staff_cross::StaffCross;
+use detection::runs::histogram::StaffMetrics;
+use geometry::staff::Staff;
+use math;
 // use nalgebra as na;
 use geometry as gm;
 use nalgebra as na;
+use std::f32;
 
 #[derive(Debug)]
 pub struct StaffCrossLine {
     pub a: StaffCross,
     pub b: StaffCross,
+
+    // The staff fitted to all of the inliers, once the model has been refined (see fit_model).
+    pub staff: Option<Staff>,
 }
 impl StaffCrossLine {
     #[inline(never)]
@@ -16,11 +23,38 @@ impl StaffCrossLine {
         StaffCrossLine {
             a: a,
             b: b,
+            staff: None,
+        }
+    }
+
+    // The centre line of the staff.
+    pub fn line(&self) -> gm::Line {
+        match self.staff {
+            Some(ref staff) => gm::Line::new(staff.pos, staff.point_at_time(staff.length)),
+            None => gm::Line::new(self.a.centre(), self.b.centre()),
+        }
+    }
+
+    // Returns the refined staff, or the staff through the two sampled crosses.
+    pub fn to_staff(&self) -> Staff {
+        match self.staff {
+            Some(ref staff) => staff.clone(),
+            None => Staff::new(
+                self.a.centre(),
+                self.b.centre(),
+                self.average_line_width(),
+                self.average_space_width(),
+                self.num_lines()
+            ),
         }
     }
 
     #[inline(never)]
     pub fn average_space_width(&self) -> f32 {
+        if let Some(ref staff) = self.staff {
+            return staff.space_width;
+        }
+
         let line = gm::Line::new(self.a.centre(), self.b.centre());
 
         let avg_a = self.a.average_space_width(&line);
@@ -29,8 +63,17 @@ impl StaffCrossLine {
         (avg_a + avg_b) / 2.0
     }
 
+    // Note: A model is only meaningful if both crosses have the same number of lines.
+    pub fn num_lines(&self) -> usize {
+        self.a.num_lines()
+    }
+
     #[inline(never)]
     pub fn average_line_width(&self) -> f32 {
+        if let Some(ref staff) = self.staff {
+            return staff.line_width;
+        }
+
         let line = gm::Line::new(self.a.centre(), self.b.centre());
 
         let avg_a = self.a.average_line_width(&line);
@@ -40,19 +83,71 @@ impl StaffCrossLine {
     }
 }
 
-pub struct StaffCrossLineModel;
+// Fits staff centre lines to staff crosses.
+// The thresholds control how closely a cross must match a model to be an inlier. Engraved scores
+// suit tight thresholds, while handwritten manuscripts need looser ones.
+#[derive(Debug, Clone, Copy)]
+pub struct StaffCrossLineModel {
+    // Maximum distance from the model line to a cross centre, relative to the space width.
+    pub max_distance: f32,
+
+    // Maximum distance for one-line staves (which have no spaces), relative to the line width.
+    pub max_single_line_distance: f32,
+
+    // Maximum |cos| of the angle between the scan direction of a cross and the model line.
+    pub max_scan_cos: f32,
+
+    // Maximum relative differences between the space (and line) widths of a cross and the model.
+    pub max_space_error: f32,
+    pub max_line_error: f32,
+
+    // The staff line and space widths of the page, if known.
+    pub staff_metrics: Option<StaffMetrics>,
+}
+
+impl Default for StaffCrossLineModel {
+    fn default() -> StaffCrossLineModel {
+        StaffCrossLineModel {
+            max_distance: 0.5,
+            max_single_line_distance: 1.0,
+            max_scan_cos: 0.8,
+            max_space_error: 0.5,
+            max_line_error: 1.0,
+            staff_metrics: None,
+        }
+    }
+}
+
 impl StaffCrossLineModel {
+    // The maximum distance from the model line to an inlier's centre.
+    pub fn max_dist(&self, num_lines: usize, space_width: f32, line_width: f32) -> f32 {
+        // Note: One-line staves have no spaces, so their line width is used instead.
+        if num_lines > 1 {
+            space_width * self.max_distance
+        } else {
+            line_width * self.max_single_line_distance
+        }
+    }
+
     #[inline(never)]
     pub fn is_inlier(
+        &self,
+        num_lines: usize,
         space_width: f32,
         line_width: f32,
         line: &gm::Line,
         line_dir: &na::Vector2<f32>,
         pt: &StaffCross) -> bool {
 
+        // Ignore if sample is from a staff with a different number of lines.
+        if pt.num_lines() != num_lines {
+            return false;
+        }
+
         // Ignore if sample is not close enough to the model.
+        let max_dist = self.max_dist(num_lines, space_width, line_width);
         let dist = line.distance_to_point(&pt.centre());
-        if dist > space_width * 0.5 {
+        if dist > max_dist {
             return false;
         }
 
@@ -61,24 +156,37 @@ impl StaffCrossLineModel {
         // being considered in the same model.
         let scan_dir = pt.scan_direction();
         let cos_angle = na::dot(&scan_dir, &line_dir);
-        if cos_angle.abs() > 0.8 {
+        if cos_angle.abs() > self.max_scan_cos {
             return false;
         }
 
         // Ignore if sample has a staff-line spacing too different from the model.
-        let pt_space_width = pt.average_space_width(&line);
-        let space_error = (pt_space_width - space_width).abs() / space_width;
-        if space_error > 0.5 {
-            return false;
+        if num_lines > 1 {
+            let pt_space_width = pt.average_space_width(&line);
+            let space_error = (pt_space_width - space_width).abs() / space_width;
+            if space_error > self.max_space_error {
+                return false;
+            }
         }
 
         // Ignore if sample has a staff-line width too different from the model.
         let pt_line_width = pt.average_line_width(&line);
         let line_error = (pt_line_width - line_width).abs() / line_width;
-        if line_error > 1.0 {
+        if line_error > self.max_line_error {
             return false;
         }
 
+        // Ignore if sample widths are inconsistent with the page's staves.
+        // Note: Widths are measured perpendicular to the model, so are not stretched.
+        if let Some(ref metrics) = self.staff_metrics {
+            if !metrics.line_width.accepts(pt_line_width, 1.0) {
+                return false;
+            }
+            if num_lines > 1 && !metrics.space_width.accepts(pt.average_space_width(&line), 1.0) {
+                return false;
+            }
+        }
+
         true
     }
 }
@@ -87,29 +195,48 @@ impl StaffCrossLineModel {
 impl RansacModel<StaffCrossLine, StaffCross> for StaffCrossLineModel {
 
     #[inline(never)]
-    fn fit_inliers(data: &[&StaffCross]) -> StaffCrossLine {
+    fn fit_inliers(&self, data: &[&StaffCross]) -> Option<StaffCrossLine> {
         let pt1 = data[0];
         let pt2 = data[1];
         // StaffCrossLine::new(pt1.centre(), pt2.centre())
-        StaffCrossLine::new(pt1.clone(), pt2.clone())
+        Some(StaffCrossLine::new(pt1.clone(), pt2.clone()))
     }
 
     #[inline(never)]
-    fn num_required() -> usize {
+    fn num_required(&self) -> usize {
         2
     }
 
+    // A line cannot be fitted through coincident centres, and crosses of staves with different
+    // numbers of lines cannot belong to the same staff.
+    fn is_degenerate(&self, data: &[&StaffCross]) -> bool {
+        let (a, b) = (data[0], data[1]);
+        if a.num_lines() != b.num_lines() {
+            return true;
+        }
+
+        let offset = b.centre() - a.centre();
+        na::dot(&offset, &offset) < 1.0
+    }
+
     #[inline(never)]
-    fn find_inliers(max_dist: f32, data: &Vec<StaffCross>, model: &StaffCrossLine) -> Vec<StaffCross> {
+    fn find_inliers(&self, data: &Vec<StaffCross>, model: &StaffCrossLine) -> Vec<StaffCross> {
+        // Crosses of staves with different numbers of lines cannot belong to the same staff:
+        if model.a.num_lines() != model.b.num_lines() {
+            return Vec::new();
+        }
+
         let space_width = model.average_space_width();
         let line_width = model.average_line_width();
-        let line = gm::Line::new(model.a.centre(), model.b.centre());
+        let line = model.line();
         let line_dir = na::normalize(&(line.b - line.a));
+        let num_lines = model.num_lines();
 
         let mut inliers = Vec::new();
 
         for pt in data {
-            let is_inlier = Self::is_inlier(
+            let is_inlier = self.is_inlier(
+                num_lines,
                 space_width,
                 line_width,
                 &line,
@@ -126,16 +253,18 @@ impl RansacModel<StaffCrossLine, StaffCross> for StaffCrossLineModel {
     }
 
     #[inline(never)]
-    fn find_outliers(max_dist: f32, data: &Vec<StaffCross>, model: &StaffCrossLine) -> Vec<StaffCross> {
+    fn find_outliers(&self, data: &Vec<StaffCross>, model: &StaffCrossLine) -> Vec<StaffCross> {
         let space_width = model.average_space_width();
         let line_width = model.average_line_width();
-        let line = gm::Line::new(model.a.centre(), model.b.centre());
+        let line = model.line();
         let line_dir = na::normalize(&(line.b - line.a));
+        let num_lines = model.num_lines();
 
         let mut inliers = Vec::new();
 
         for pt in data {
-            let is_inlier = Self::is_inlier(
+            let is_inlier = self.is_inlier(
+                num_lines,
                 space_width,
                 line_width,
                 &line,
@@ -151,9 +280,86 @@ impl RansacModel<StaffCrossLine, StaffCross> for StaffCrossLineModel {
         inliers
     }
 
+    // Fits a staff to all of the crosses, weighting each cross by its score.
+    // The staff spans the crosses, and its line and space widths are the weighted averages of
+    // those of the crosses.
     #[inline(never)]
-    fn fit_model(data: &Vec<StaffCross>) -> Option<StaffCrossLine> {
-        None
+    fn fit_model(&self, data: &Vec<StaffCross>) -> Option<StaffCrossLine> {
+        if data.len() < self.num_required() {
+            return None;
+        }
+
+        let num_lines = data[0].num_lines();
+        if data.iter().any(|pt| pt.num_lines() != num_lines) {
+            return None;
+        }
+
+        let centres : Vec<na::Vector2<f32>> = data.iter().map(|pt| pt.centre()).collect();
+        let weights : Vec<f32> = data.iter().map(|pt| pt.score()).collect();
+        // Note: Staves may be at any angle, so the line is fitted by total least squares.
+        let line = match math::fit_line_total_weighted(&centres, &weights) {
+            Some(line) => line,
+            None => return None,
+        };
+
+        let weight_sum = weights.iter().fold(0.0, |sum, w| sum + w);
+        if weight_sum <= 0.0 {
+            return None;
+        }
+        let weighted_average = |f: &Fn(&StaffCross) -> f32| {
+            data.iter().zip(weights.iter()).fold(0.0, |sum, (pt, w)| sum + f(pt) * w) / weight_sum
+        };
+        let space_width = weighted_average(&|pt| pt.average_space_width(&line));
+        let line_width = weighted_average(&|pt| pt.average_line_width(&line));
+
+        // Find the extent of the crosses along the line:
+        let line_dir = na::normalize(&(line.b - line.a));
+        let times : Vec<f32> = centres.iter().map(|c| na::dot(&(*c - line.a), &line_dir)).collect();
+        let mut first = 0;
+        let mut last = 0;
+        for (i, &t) in times.iter().enumerate() {
+            if t < times[first] {
+                first = i;
+            }
+            if t > times[last] {
+                last = i;
+            }
+        }
+        if times[last] - times[first] < 1.0 {
+            return None;
+        }
+
+        let staff = Staff::new(
+            line.a + line_dir * times[first],
+            line.a + line_dir * times[last],
+            line_width,
+            space_width,
+            num_lines
+        );
+
+        Some(StaffCrossLine {
+            a: data[first].clone(),
+            b: data[last].clone(),
+            staff: Some(staff),
+        })
+    }
+
+    // Crosses that more closely resemble staves support a model more strongly.
+    fn point_weight(&self, pt: &StaffCross) -> f32 {
+        pt.score()
+    }
+
+    // The distance from the model line to the cross centre, relative to the inlier threshold.
+    fn residual(&self, model: &StaffCrossLine, pt: &StaffCross) -> f32 {
+        let num_lines = model.num_lines();
+        if pt.num_lines() != num_lines || model.b.num_lines() != num_lines {
+            return f32::INFINITY;
+        }
+
+        let line = model.line();
+        let max_dist = self.max_dist(num_lines, model.average_space_width(), model.average_line_width());
+
+        line.distance_to_point(&pt.centre()) / max_dist
     }
 
 }
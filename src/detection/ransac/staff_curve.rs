@@ -0,0 +1,191 @@
+use detection::ransac::RansacModel;
+use detection::ransac::staff_cross::StaffCrossLineModel;
+use detection::scanning::staff_cross::StaffCross;
+use geometry::curve::{Curve, BezierSpline};
+use geometry::curved_staff::CurvedStaff;
+use geometry as gm;
+use math;
+use nalgebra as na;
+use std::f32;
+use std::cmp;
+
+// Samples whose crosses are closer together than this along the staff cannot constrain a curve.
+const MIN_SAMPLE_SEPARATION : f32 = 1.0;
+
+// Fits curved staff centre lines (piecewise cubic Bezier curves) to staff crosses.
+// Ported from fitCubicBezierRansac in web/Curves.elm.
+#[derive(Debug, Clone, Copy)]
+pub struct StaffCurveModel {
+    // Number of crosses each hypothesis is fitted to.
+    // Note: A cubic curve needs 4 points, but fitting to more makes hypotheses less sensitive to
+    // noise in the cross centres.
+    pub num_samples: usize,
+
+    // Maximum number of curve segments in a refined model (see fit_model). Each segment requires
+    // at least min_segment_crosses crosses.
+    pub num_segments: usize,
+    pub min_segment_crosses: usize,
+
+    // Inlier thresholds, applied along the tangent to the curve at the closest point to each cross.
+    pub thresholds: StaffCrossLineModel,
+}
+
+impl Default for StaffCurveModel {
+    fn default() -> StaffCurveModel {
+        StaffCurveModel {
+            num_samples: 5,
+            num_segments: 1,
+            min_segment_crosses: 8,
+            thresholds: StaffCrossLineModel::default(),
+        }
+    }
+}
+
+impl StaffCurveModel {
+    // The tangent line to the staff's centre line at the closest point to a cross.
+    fn local_line(staff: &CurvedStaff, pt: &StaffCross) -> gm::Line {
+        let t = staff.centreline.closest_time(&pt.centre());
+        let p = staff.centreline.point_at(t);
+        gm::Line::new(p, p + staff.centreline.tangent_at(t))
+    }
+
+    // Fits a staff to crosses, weighting each cross by its score.
+    pub fn fit_staff(&self, data: &[&StaffCross], num_segments: usize) -> Option<CurvedStaff> {
+        if data.len() < 4 {
+            return None;
+        }
+
+        let num_lines = data[0].num_lines();
+        if data.iter().any(|pt| pt.num_lines() != num_lines) {
+            return None;
+        }
+
+        let centres : Vec<na::Vector2<f32>> = data.iter().map(|pt| pt.centre()).collect();
+        let weights : Vec<f32> = data.iter().map(|pt| pt.score()).collect();
+        let centreline = match BezierSpline::fit_to_points(&centres, &weights, num_segments) {
+            Some(centreline) => centreline,
+            None => return None,
+        };
+
+        // Measure the crosses along the curve:
+        let mut staff = CurvedStaff::new(centreline, 0.0, 0.0, num_lines);
+        if staff.length < 1.0 {
+            return None;
+        }
+
+        let weight_sum = weights.iter().fold(0.0, |sum, w| sum + w);
+        if weight_sum <= 0.0 {
+            return None;
+        }
+        let mut space_width_sum = 0.0;
+        let mut line_width_sum = 0.0;
+        for (pt, w) in data.iter().zip(weights.iter()) {
+            let line = Self::local_line(&staff, pt);
+            space_width_sum += pt.average_space_width(&line) * w;
+            line_width_sum += pt.average_line_width(&line) * w;
+        }
+        staff.space_width = space_width_sum / weight_sum;
+        staff.line_width = line_width_sum / weight_sum;
+
+        Some(staff)
+    }
+
+    pub fn is_inlier(&self, model: &CurvedStaff, pt: &StaffCross) -> bool {
+        let line = Self::local_line(model, pt);
+        let line_dir = na::normalize(&(line.b - line.a));
+
+        self.thresholds.is_inlier(
+            model.num_lines,
+            model.space_width,
+            model.line_width,
+            &line,
+            &line_dir,
+            pt
+        )
+    }
+}
+
+impl RansacModel<CurvedStaff, StaffCross> for StaffCurveModel {
+
+    // Note: is_degenerate only rejects samples cheaply, so a sample that passes it may still fail
+    // to fit (e.g. if its curve is too short).
+    fn fit_inliers(&self, data: &[&StaffCross]) -> Option<CurvedStaff> {
+        self.fit_staff(data, 1)
+    }
+
+    fn num_required(&self) -> usize {
+        cmp::max(4, self.num_samples)
+    }
+
+    // A sample is degenerate if its crosses have different numbers of lines, or fewer than 4 of
+    // them are at distinct positions along the staff.
+    // Note: This is checked for every hypothesis before they are fitted in parallel, so avoids
+    // fitting the curve itself.
+    fn is_degenerate(&self, data: &[&StaffCross]) -> bool {
+        if data.len() < 4 {
+            return true;
+        }
+
+        let num_lines = data[0].num_lines();
+        if data.iter().any(|pt| pt.num_lines() != num_lines) {
+            return true;
+        }
+
+        let centres : Vec<na::Vector2<f32>> = data.iter().map(|pt| pt.centre()).collect();
+        let weights = vec![1.0; centres.len()];
+        let axis = match math::principal_axis(&centres, &weights) {
+            Some((_, axis)) => axis,
+            None => return true,
+        };
+
+        let mut projections : Vec<f32> = centres.iter().map(|pt| na::dot(pt, &axis)).collect();
+        projections.sort_by(|a, b| a.partial_cmp(b).unwrap_or(cmp::Ordering::Equal));
+
+        let mut num_distinct = 1;
+        let mut last = projections[0];
+        for &p in &projections[1..] {
+            if p - last >= MIN_SAMPLE_SEPARATION {
+                num_distinct += 1;
+                last = p;
+            }
+        }
+
+        num_distinct < 4
+    }
+
+    fn find_inliers(&self, data: &Vec<StaffCross>, model: &CurvedStaff) -> Vec<StaffCross> {
+        data.iter().filter(|pt| self.is_inlier(model, pt)).cloned().collect()
+    }
+
+    fn find_outliers(&self, data: &Vec<StaffCross>, model: &CurvedStaff) -> Vec<StaffCross> {
+        data.iter().filter(|pt| !self.is_inlier(model, pt)).cloned().collect()
+    }
+
+    // Fits as many segments as the number of crosses allows, up to num_segments.
+    // Note: Gaps in the crosses (e.g. under a busy passage) can leave a segment with too few
+    // crosses to fit, so fewer segments are tried before giving up.
+    fn fit_model(&self, data: &Vec<StaffCross>) -> Option<CurvedStaff> {
+        let refs : Vec<&StaffCross> = data.iter().collect();
+        let max_segments = cmp::max(1, cmp::min(self.num_segments, data.len() / cmp::max(1, self.min_segment_crosses)));
+
+        (1..max_segments + 1).rev().filter_map(|num_segments| self.fit_staff(&refs, num_segments)).next()
+    }
+
+    fn point_weight(&self, pt: &StaffCross) -> f32 {
+        pt.score()
+    }
+
+    // The distance from the local tangent line to the cross centre (as used by is_inlier),
+    // relative to the inlier threshold.
+    // Note: Beyond the ends of the curve, this is the distance to the extended end tangent, not to
+    // the end point.
+    fn residual(&self, model: &CurvedStaff, pt: &StaffCross) -> f32 {
+        if pt.num_lines() != model.num_lines {
+            return f32::INFINITY;
+        }
+
+        let line = Self::local_line(model, pt);
+        let max_dist = self.thresholds.max_dist(model.num_lines, model.space_width, model.line_width);
+        line.distance_to_point(&pt.centre()) / max_dist
+    }
+}
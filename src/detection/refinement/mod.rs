@@ -1,16 +1,87 @@
-use geometry::staff::Staff;
-use ffmpeg_camera::image::Image;
+use geometry::staff::StaffPath;
+use ffmpeg_camera::image::{Image, SampleMode, BorderPolicy};
+use detection::binarisation::{Binarizer, FixedThreshold};
 use std;
+use nalgebra as na;
 
-pub fn staff_sample_average<I: Image>(image: &I, staff: &Staff, t: f32, num_samples: usize, sample_sep: f32) -> f32 {
-    let mut blank_avg = 0.0;
-    for pt in staff.perpendicular_samples(t, num_samples, sample_sep) {
-        let brightness = image.sample_point(pt).y as f32 / 255.0;
-        blank_avg += brightness.round();
+static DEFAULT_BINARIZER : FixedThreshold = FixedThreshold { threshold: 128.0 };
+
+// Controls how image intensities are sampled when gathering staff evidence.
+#[derive(Clone, Copy, Debug)]
+pub struct SampleParams<'a> {
+    pub mode: SampleMode,
+    pub border: BorderPolicy,
+
+    // Classifies each sample as black (0.0) or white (1.0). If None, samples are continuous
+    // intensities in [0, 1].
+    pub binarizer: Option<&'a Binarizer>,
+}
+
+impl<'a> Default for SampleParams<'a> {
+    fn default() -> SampleParams<'a> {
+        SampleParams {
+            mode: SampleMode::Nearest,
+            border: BorderPolicy::Clamp,
+            binarizer: Some(&DEFAULT_BINARIZER),
+        }
     }
-    blank_avg /= num_samples as f32;
+}
 
-    blank_avg
+// Returns the brightness of a point in [0, 1], or None if the sample is missing.
+pub fn sample_brightness<I: Image>(image: &I, pt: na::Vector2<f32>, params: &SampleParams) -> Option<f32> {
+    image.sample_luma(pt, params.mode, params.border).map(|luma| {
+        match params.binarizer {
+            Some(binarizer) => if luma < binarizer.threshold_at(pt) { 0.0 } else { 1.0 },
+            None => luma / 255.0,
+        }
+    })
+}
+
+pub fn staff_sample_average<I: Image, S: StaffPath>(image: &I, staff: &S, t: f32, num_samples: usize, sample_sep: f32) -> f32 {
+    staff_sample_average_with(image, staff, t, num_samples, sample_sep, &SampleParams::default())
+}
+
+// Missing samples are ignored. If every sample is missing, the region is treated as blank (1.0).
+pub fn staff_sample_average_with<I: Image, S: StaffPath>(image: &I, staff: &S, t: f32, num_samples: usize, sample_sep: f32, params: &SampleParams) -> f32 {
+    sample_average(image, &staff.perpendicular_samples(t, num_samples, sample_sep), params).unwrap_or(1.0)
+}
+
+// Returns the average brightness of the given points, or None if every sample is missing.
+fn sample_average<I: Image>(image: &I, points: &[na::Vector2<f32>], params: &SampleParams) -> Option<f32> {
+    let mut sum = 0.0;
+    let mut count = 0;
+    for pt in points {
+        if let Some(brightness) = sample_brightness(image, *pt, params) {
+            sum += brightness;
+            count += 1;
+        }
+    }
+
+    if count == 0 {
+        return None;
+    }
+
+    Some(sum / count as f32)
+}
+
+// Returns the number of points sampled by staff_space_samples for a staff with num_lines lines.
+pub fn num_staff_space_samples(num_lines: usize) -> usize {
+    match num_lines {
+        0 => 0,
+        1 => 2,
+        n => n - 1,
+    }
+}
+
+// Points across the staff at the given time that should be white: the middle of each space, or
+// for a single line staff (e.g. percussion), the bands on either side of the line.
+pub fn staff_space_samples<S: StaffPath>(staff: &S, t: f32) -> Vec<na::Vector2<f32>> {
+    match staff.num_lines() {
+        0 => Vec::new(),
+        // Note: These lie at +/- line_sep.
+        1 => staff.perpendicular_samples(t, 2, staff.line_sep() * 2.0),
+        n => staff.perpendicular_samples(t, n - 1, staff.line_sep()),
+    }
 }
 
 #[derive(PartialEq, Clone, Copy)]
@@ -66,7 +137,12 @@ pub fn classify_staff_sample(line_avg: f32, space_avg: f32, blank_avg: f32) -> S
     StaffEvidenceClass::None
 }
 
-pub fn partition_staff<I: Image>(image: &I, staff: &Staff) -> (Vec<Staff>, Vec<Staff>) {
+// Note: Curved staves (e.g. geometry::curved_staff::CurvedStaff) are followed along their curves.
+pub fn partition_staff<I: Image, S: StaffPath>(image: &I, staff: &S) -> (Vec<S>, Vec<S>) {
+    partition_staff_with(image, staff, &SampleParams::default())
+}
+
+pub fn partition_staff_with<I: Image, S: StaffPath>(image: &I, staff: &S, params: &SampleParams) -> (Vec<S>, Vec<S>) {
 
     // TODO: Replace all of this with a more generic classifier.
 
@@ -79,13 +155,15 @@ pub fn partition_staff<I: Image>(image: &I, staff: &Staff) -> (Vec<Staff>, Vec<S
     while t + step_size < t_max {
         t += step_size;
 
-        let line_avg = staff_sample_average(image, staff, t, 5, staff.line_sep());
-        let space_avg = staff_sample_average(image, staff, t, 4, staff.line_sep());
+        let line_avg = staff_sample_average_with(image, staff, t, staff.num_lines(), staff.line_sep(), params);
+        // Note: Missing space samples are neutral evidence, rather than evidence of white spaces.
+        let space_avg = sample_average(image, &staff_space_samples(staff, t), params).unwrap_or(0.5);
 
         // Blank spaces:
         let blank_samples = 20;
-        let blank_sep = 1.2 * staff.line_sep() * 2.0 / (blank_samples as f32 * 0.5);
-        let blank_avg = staff_sample_average(image, staff, t, blank_samples, blank_sep);
+        let half_span = staff.line_sep() * ((staff.num_lines() as f32 - 1.0) / 2.0).max(1.0);
+        let blank_sep = 1.2 * half_span / (blank_samples as f32 * 0.5);
+        let blank_avg = staff_sample_average_with(image, staff, t, blank_samples, blank_sep, params);
 
         let class = classify_staff_sample(line_avg, space_avg, blank_avg);
 
@@ -95,8 +173,7 @@ pub fn partition_staff<I: Image>(image: &I, staff: &Staff) -> (Vec<Staff>, Vec<S
     // Use class information to partition samples:
     let mut sample_blank_spans = Vec::new();
     let mut sample_staff_spans = Vec::new();
-    let staff_height = staff.line_width * 5.0 + staff.space_width * 4.0;
-    let min_gap_width = staff_height;
+    let min_gap_width = staff.height();
     let min_gap_samples = std::cmp::max(2, ((min_gap_width) / step_size).round() as usize);
     let min_span_samples = min_gap_samples;
     let max_weak_gap_samples = min_gap_samples;
@@ -167,10 +244,7 @@ pub fn partition_staff<I: Image>(image: &I, staff: &Staff) -> (Vec<Staff>, Vec<S
             let t_start = t_min + (1 + start) as f32 * step_size;
             let t_end = t_min + (1 + end) as f32 * step_size;
 
-            let p_start = staff.point_at_time(t_start);
-            let p_end = staff.point_at_time(t_end);
-
-            let segment = Staff::new(p_start, p_end, staff.line_width, staff.space_width);
+            let segment = staff.sub_staff(t_start, t_end);
             staff_segments.push(segment);
         }
         staff_segments
@@ -182,43 +256,63 @@ pub fn partition_staff<I: Image>(image: &I, staff: &Staff) -> (Vec<Staff>, Vec<S
     (staff_segments, blank_segments)
 }
 
-pub fn staff_segment_is_valid<I: Image>(image: &I, staff: &Staff) -> bool {
+pub fn staff_segment_is_valid<I: Image, S: StaffPath>(image: &I, staff: &S) -> bool {
+    staff_segment_is_valid_with(image, staff, &SampleParams::default())
+}
+
+pub fn staff_segment_is_valid_with<I: Image, S: StaffPath>(image: &I, staff: &S, params: &SampleParams) -> bool {
 
     // Calculate averages along each staff line and space.
     // Consider the segment invalid if any single line or space is not present often enough.
 
-    let mut line_sums = [0.0; 5];
-    let mut space_sums = [0.0; 4];
+    let num_lines = staff.num_lines();
+    if num_lines == 0 {
+        return false;
+    }
+
+    let num_spaces = num_staff_space_samples(num_lines);
+    let mut line_sums = vec![0.0; num_lines];
+    let mut line_counts = vec![0; num_lines];
+    let mut space_sums = vec![0.0; num_spaces];
+    let mut space_counts = vec![0; num_spaces];
 
     let step_size = staff.line_sep() * 0.5;
     let mut t = 0.0;
-    let mut num_samples = 0;
-    while t + step_size < staff.length {
+    while t + step_size < staff.length() {
         t += step_size;
-        num_samples += 1;
 
-        for (i, pt) in staff.perpendicular_samples(t, 5, staff.line_sep()).iter().enumerate() {
-            let brightness = image.sample_point(*pt).y as f32 / 255.0;
-            line_sums[i] += brightness.round();
+        for (i, pt) in staff.perpendicular_samples(t, num_lines, staff.line_sep()).iter().enumerate() {
+            if let Some(brightness) = sample_brightness(image, *pt, params) {
+                line_sums[i] += brightness;
+                line_counts[i] += 1;
+            }
         }
 
-        for (i, pt) in staff.perpendicular_samples(t, 4, staff.line_sep()).iter().enumerate() {
-            let brightness = image.sample_point(*pt).y as f32 / 255.0;
-            space_sums[i] += brightness.round();
+        for (i, pt) in staff_space_samples(staff, t).iter().enumerate() {
+            if let Some(brightness) = sample_brightness(image, *pt, params) {
+                space_sums[i] += brightness;
+                space_counts[i] += 1;
+            }
         }
     }
 
     // Ensure each line is dark enough:
-    for sum in line_sums.iter().cloned() {
-        let avg = sum as f32 / num_samples as f32;
+    for (sum, count) in line_sums.iter().cloned().zip(line_counts.iter().cloned()) {
+        if count == 0 {
+            return false;
+        }
+        let avg = sum as f32 / count as f32;
         if avg > 0.25 {
             return false;
         }
     }
 
     // Ensure each space is bright enough:
-    for sum in space_sums.iter().cloned() {
-        let avg = sum as f32 / num_samples as f32;
+    for (sum, count) in space_sums.iter().cloned().zip(space_counts.iter().cloned()) {
+        if count == 0 {
+            return false;
+        }
+        let avg = sum as f32 / count as f32;
         if avg < 0.33 {
             return false;
         }
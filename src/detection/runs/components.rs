@@ -0,0 +1,162 @@
+use nalgebra as na;
+use std::cmp;
+
+use ffmpeg_camera::image_view::Region;
+use detection::runs::run_image::{Run, RunImage, Orientation};
+
+// Which neighbouring pixels are considered connected.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Connectivity {
+    // Edge neighbours only.
+    Four,
+    // Edge and corner neighbours.
+    Eight,
+}
+
+// A connected set of black pixels.
+#[derive(Clone, Debug)]
+pub struct Component {
+    // The runs making up the component, in the orientation of the RunImage they came from.
+    pub runs: Vec<Run>,
+    pub orientation: Orientation,
+
+    // The number of pixels in the component.
+    pub area: usize,
+    pub bounding_box: Region,
+
+    // The mean of the component's pixel centres.
+    pub centroid: na::Vector2<f32>,
+}
+
+impl Component {
+    fn from_runs(runs: Vec<Run>, orientation: Orientation) -> Component {
+        let mut area = 0;
+        let mut sum_along = 0.0;
+        let mut sum_across = 0.0;
+        let mut min_line = usize::max_value();
+        let mut max_line = 0;
+        let mut min_pos = usize::max_value();
+        let mut max_pos = 0;
+
+        for run in &runs {
+            let len = run.length();
+            area += len;
+            // The sum of the pixel centres along the run is len * the run's centre:
+            sum_along += len as f32 * (run.start + run.end) as f32 / 2.0;
+            sum_across += len as f32 * (run.line as f32 + 0.5);
+
+            min_line = cmp::min(min_line, run.line);
+            max_line = cmp::max(max_line, run.line);
+            min_pos = cmp::min(min_pos, run.start);
+            max_pos = cmp::max(max_pos, run.end);
+        }
+
+        let mean_along = sum_along / area as f32;
+        let mean_across = sum_across / area as f32;
+
+        let (centroid, bounding_box) = match orientation {
+            Orientation::Horizontal => (
+                na::Vector2::new(mean_along, mean_across),
+                Region::new(min_pos, min_line, max_pos - min_pos, max_line - min_line + 1)
+            ),
+            Orientation::Vertical => (
+                na::Vector2::new(mean_across, mean_along),
+                Region::new(min_line, min_pos, max_line - min_line + 1, max_pos - min_pos)
+            ),
+        };
+
+        Component {
+            runs: runs,
+            orientation: orientation,
+            area: area,
+            bounding_box: bounding_box,
+            centroid: centroid,
+        }
+    }
+}
+
+fn find_root(parents: &mut Vec<usize>, i: usize) -> usize {
+    let mut root = i;
+    while parents[root] != root {
+        root = parents[root];
+    }
+
+    // Compress the path:
+    let mut j = i;
+    while parents[j] != root {
+        let next = parents[j];
+        parents[j] = root;
+        j = next;
+    }
+
+    root
+}
+
+fn union(parents: &mut Vec<usize>, a: usize, b: usize) {
+    let root_a = find_root(parents, a);
+    let root_b = find_root(parents, b);
+    if root_a != root_b {
+        parents[cmp::max(root_a, root_b)] = cmp::min(root_a, root_b);
+    }
+}
+
+// Labels the connected components of a run image.
+// Components are returned in order of their first run (i.e. top to bottom for horizontal runs,
+// and left to right for vertical runs).
+pub fn connected_components(runs: &RunImage, connectivity: Connectivity) -> Vec<Component> {
+    // Index every run, recording where each line's runs begin:
+    let mut all_runs = Vec::new();
+    let mut line_starts = Vec::with_capacity(runs.num_lines() + 1);
+    for line in 0..runs.num_lines() {
+        line_starts.push(all_runs.len());
+        all_runs.extend_from_slice(runs.runs(line));
+    }
+    line_starts.push(all_runs.len());
+
+    // With 8-connectivity, runs that touch diagonally are connected:
+    let reach = match connectivity {
+        Connectivity::Four => 0,
+        Connectivity::Eight => 1,
+    };
+
+    // Join overlapping runs in adjacent lines:
+    let mut parents : Vec<usize> = (0..all_runs.len()).collect();
+    for line in 1..runs.num_lines() {
+        let (prev_start, prev_end) = (line_starts[line - 1], line_starts[line]);
+        let (curr_start, curr_end) = (line_starts[line], line_starts[line + 1]);
+
+        let mut p = prev_start;
+        let mut c = curr_start;
+        while p < prev_end && c < curr_end {
+            let prev = all_runs[p];
+            let curr = all_runs[c];
+
+            if prev.start < curr.end + reach && curr.start < prev.end + reach {
+                union(&mut parents, p, c);
+            }
+
+            // Advance whichever run ends first:
+            if prev.end < curr.end {
+                p += 1;
+            } else {
+                c += 1;
+            }
+        }
+    }
+
+    // Gather the runs of each component:
+    let mut component_indices = vec![usize::max_value(); all_runs.len()];
+    let mut component_runs : Vec<Vec<Run>> = Vec::new();
+    for i in 0..all_runs.len() {
+        let root = find_root(&mut parents, i);
+        if component_indices[root] == usize::max_value() {
+            component_indices[root] = component_runs.len();
+            component_runs.push(Vec::new());
+        }
+        component_runs[component_indices[root]].push(all_runs[i]);
+    }
+
+    component_runs.into_iter()
+        .map(|runs_i| Component::from_runs(runs_i, runs.orientation))
+        .collect()
+}
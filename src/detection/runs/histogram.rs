@@ -0,0 +1,138 @@
+use std::cmp;
+
+use ffmpeg_camera::image::Image;
+use detection::binarisation::Binarizer;
+use detection::runs::run_image::{RunImage, Orientation};
+
+// Estimates with a lower confidence than this are considered unreliable, and are ignored when
+// checking measurements against them.
+pub const MIN_CONFIDENCE : f32 = 0.25;
+
+// Histograms of run lengths over a whole run image.
+// Note: Runs that touch the edge of the image are cut off, so they are not counted.
+#[derive(Clone, Debug)]
+pub struct RunHistograms {
+    // The number of black (and white) runs of each length, indexed by length.
+    pub black: Vec<usize>,
+    pub white: Vec<usize>,
+}
+
+impl RunHistograms {
+    pub fn new(runs: &RunImage) -> RunHistograms {
+        let line_length = runs.line_length();
+        let mut black = vec![0; line_length + 1];
+        let mut white = vec![0; line_length + 1];
+
+        let is_bounded = |start: usize, end: usize| start > 0 && end < line_length;
+
+        for line in 0..runs.num_lines() {
+            for run in runs.runs(line) {
+                if is_bounded(run.start, run.end) {
+                    black[run.length()] += 1;
+                }
+            }
+            for run in runs.white_runs(line) {
+                if is_bounded(run.start, run.end) {
+                    white[run.length()] += 1;
+                }
+            }
+        }
+
+        RunHistograms {
+            black: black,
+            white: white,
+        }
+    }
+}
+
+// The most common value of a histogram.
+#[derive(Copy, Clone, Debug)]
+pub struct ModeEstimate {
+    pub value: f32,
+
+    // The fraction of all counts that lie close to the mode, in [0, 1].
+    pub confidence: f32,
+}
+
+impl ModeEstimate {
+    pub fn from_histogram(histogram: &[usize]) -> ModeEstimate {
+        let total = histogram.iter().fold(0, |sum, &count| sum + count);
+        if total == 0 {
+            return ModeEstimate { value: 0.0, confidence: 0.0 };
+        }
+
+        let mut mode = 0;
+        for (length, &count) in histogram.iter().enumerate() {
+            if count > histogram[mode] {
+                mode = length;
+            }
+        }
+
+        // Run lengths are quantised, so allow at least a pixel either side of the mode:
+        let tolerance = cmp::max(1, mode / 5);
+        let lo = mode.saturating_sub(tolerance);
+        let hi = cmp::min(histogram.len() - 1, mode + tolerance);
+        let near_mode = histogram[lo..hi + 1].iter().fold(0, |sum, &count| sum + count);
+
+        ModeEstimate {
+            value: mode as f32,
+            confidence: near_mode as f32 / total as f32,
+        }
+    }
+
+    pub fn is_confident(&self) -> bool {
+        self.confidence >= MIN_CONFIDENCE
+    }
+
+    // Returns whether a measured width is consistent with the estimate.
+    // Measurements along a line oblique to the staff are longer than the true width, so widths up
+    // to max_stretch times longer are accepted.
+    // Note: Unreliable estimates accept every measurement.
+    pub fn accepts(&self, width: f32, max_stretch: f32) -> bool {
+        if !self.is_confident() {
+            return true;
+        }
+
+        let min_width = self.value * 0.5 - 1.0;
+        let max_width = self.value * 1.5 * max_stretch + 1.0;
+
+        min_width <= width && width <= max_width
+    }
+}
+
+// The dominant staff line thickness and staff space height of a page.
+#[derive(Copy, Clone, Debug)]
+pub struct StaffMetrics {
+    pub line_width: ModeEstimate,
+    pub space_width: ModeEstimate,
+}
+
+impl StaffMetrics {
+    pub fn from_histograms(histograms: &RunHistograms) -> StaffMetrics {
+        StaffMetrics {
+            line_width: ModeEstimate::from_histogram(&histograms.black),
+            space_width: ModeEstimate::from_histogram(&histograms.white),
+        }
+    }
+
+    // Returns the metrics of an image that is larger by the given scale (e.g. to map metrics of a
+    // base image to a level of an image pyramid, use a scale < 1).
+    pub fn scaled(&self, scale: f32) -> StaffMetrics {
+        StaffMetrics {
+            line_width: ModeEstimate { value: self.line_width.value * scale, .. self.line_width },
+            space_width: ModeEstimate { value: self.space_width.value * scale, .. self.space_width },
+        }
+    }
+}
+
+// Estimates the staff line thickness and staff space height of an image from the lengths of the
+// vertical black and white runs over the whole image (the staff lines and spaces make up most
+// of a page of music, so the most common run lengths are those of the staves).
+// Note: This assumes that staves are roughly horizontal.
+pub fn staff_metrics<I: Image>(image: &I, binarizer: &Binarizer) -> (RunHistograms, StaffMetrics) {
+    let runs = RunImage::new(image, binarizer, Orientation::Vertical);
+    let histograms = RunHistograms::new(&runs);
+    let metrics = StaffMetrics::from_histograms(&histograms);
+
+    (histograms, metrics)
+}
@@ -0,0 +1,3 @@
+pub mod run_image;
+pub mod components;
+pub mod histogram;
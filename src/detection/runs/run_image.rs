@@ -0,0 +1,160 @@
+use ffmpeg_camera::image::{Image, FromRawParts};
+use ffmpeg_camera::image_grey;
+use detection::binarisation::Binarizer;
+
+// The direction in which runs are encoded.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Orientation {
+    // Runs along image rows.
+    Horizontal,
+    // Runs along image columns.
+    Vertical,
+}
+
+// A run of consecutive black pixels along a row (or column) of an image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Run {
+    // The row (or column) containing the run.
+    pub line: usize,
+
+    // The first pixel of the run, and the pixel after its last.
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Run {
+    pub fn length(&self) -> usize {
+        self.end - self.start
+    }
+
+    // Returns the (col, row) index of a pixel in the run, for a run image of the given orientation.
+    pub fn pixel(&self, orientation: Orientation, i: usize) -> [usize; 2] {
+        match orientation {
+            Orientation::Horizontal => [self.start + i, self.line],
+            Orientation::Vertical => [self.line, self.start + i],
+        }
+    }
+}
+
+// A run-length encoded binary image.
+#[derive(Clone, Debug)]
+pub struct RunImage {
+    pub width: usize,
+    pub height: usize,
+    pub orientation: Orientation,
+
+    // The black runs in each row (or column), in order.
+    lines: Vec<Vec<Run>>,
+}
+
+impl RunImage {
+    pub fn new<I: Image>(image: &I, binarizer: &Binarizer, orientation: Orientation) -> RunImage {
+        let (num_lines, line_length) = match orientation {
+            Orientation::Horizontal => (image.height(), image.width()),
+            Orientation::Vertical => (image.width(), image.height()),
+        };
+
+        let mut lines = Vec::with_capacity(num_lines);
+        for line in 0..num_lines {
+            let mut runs = Vec::new();
+            let mut run_start = None;
+            for i in 0..line_length {
+                let (col, row) = match orientation {
+                    Orientation::Horizontal => (i, line),
+                    Orientation::Vertical => (line, i),
+                };
+                let is_black = binarizer.is_black(col, row, image.luma(col, row));
+
+                match (is_black, run_start) {
+                    (true, None) => run_start = Some(i),
+                    (false, Some(start)) => {
+                        runs.push(Run { line: line, start: start, end: i });
+                        run_start = None;
+                    },
+                    _ => {},
+                }
+            }
+            if let Some(start) = run_start {
+                runs.push(Run { line: line, start: start, end: line_length });
+            }
+
+            lines.push(runs);
+        }
+
+        RunImage {
+            width: image.width(),
+            height: image.height(),
+            orientation: orientation,
+            lines: lines,
+        }
+    }
+
+    // The number of rows (or columns) in the image.
+    pub fn num_lines(&self) -> usize {
+        self.lines.len()
+    }
+
+    // The length of each row (or column).
+    pub fn line_length(&self) -> usize {
+        match self.orientation {
+            Orientation::Horizontal => self.width,
+            Orientation::Vertical => self.height,
+        }
+    }
+
+    pub fn runs(&self, line: usize) -> &[Run] {
+        &self.lines[line]
+    }
+
+    pub fn all_runs<'a>(&'a self) -> Box<Iterator<Item=&'a Run> + 'a> {
+        Box::new(self.lines.iter().flat_map(|runs| runs.iter()))
+    }
+
+    pub fn num_runs(&self) -> usize {
+        self.lines.iter().fold(0, |sum, runs| sum + runs.len())
+    }
+
+    // Returns the runs of white pixels in a row (or column).
+    pub fn white_runs(&self, line: usize) -> Vec<Run> {
+        let mut white_runs = Vec::new();
+        let mut start = 0;
+        for run in &self.lines[line] {
+            if run.start > start {
+                white_runs.push(Run { line: line, start: start, end: run.start });
+            }
+            start = run.end;
+        }
+        if start < self.line_length() {
+            white_runs.push(Run { line: line, start: start, end: self.line_length() });
+        }
+
+        white_runs
+    }
+
+    pub fn is_black(&self, col: usize, row: usize) -> bool {
+        let (line, i) = match self.orientation {
+            Orientation::Horizontal => (row, col),
+            Orientation::Vertical => (col, row),
+        };
+
+        let runs = &self.lines[line];
+        match runs.binary_search_by(|run| run.start.cmp(&i)) {
+            Ok(_) => true,
+            Err(0) => false,
+            Err(next) => i < runs[next - 1].end,
+        }
+    }
+
+    // Returns the image with black pixels set to 0, and white pixels to 255 (e.g. for saving).
+    pub fn to_grey_image(&self) -> image_grey::Image<'static> {
+        let mut data = vec![255; self.width * self.height];
+        for run in self.all_runs() {
+            for i in 0..run.length() {
+                let pixel = run.pixel(self.orientation, i);
+                data[pixel[1] * self.width + pixel[0]] = 0;
+            }
+        }
+
+        <image_grey::Image as FromRawParts>::from_raw_parts(self.width, self.height, data)
+    }
+}
@@ -1,12 +1,77 @@
 // use ffmpeg_camera::image_ycbcr;
 use ffmpeg_camera::image::Image;
-use ffmpeg_camera::image;
+use detection::binarisation::Binarizer;
+use nalgebra as na;
 use std::cmp;
+use std::f32;
+
+// A line through an image along which pixels are scanned.
+// This is a rigid (SE(2)) scan frame: scan coordinate t maps to the image point origin + dir * t.
+// Image points are continuous, with pixel (col, row) covering [col, col+1) x [row, row+1).
+#[derive(Debug, Clone, Copy)]
+pub struct ScanLine {
+    pub origin: na::Vector2<f32>,
+    pub dir: na::Vector2<f32>,
+
+    // The range of t over which the line lies within the image.
+    pub t_min: f32,
+    pub t_max: f32,
+}
+
+impl ScanLine {
+    // Returns the part of the line through origin in direction dir (a unit vector) that lies
+    // within an image of the given size, or None if the line misses the image.
+    pub fn clipped(origin: na::Vector2<f32>, dir: na::Vector2<f32>, width: usize, height: usize) -> Option<ScanLine> {
+        let mut t_min = f32::NEG_INFINITY;
+        let mut t_max = f32::INFINITY;
+
+        let size = [width as f32, height as f32];
+        for i in 0..2 {
+            if dir[i].abs() < 1e-6 {
+                if origin[i] < 0.0 || origin[i] >= size[i] {
+                    return None;
+                }
+                continue;
+            }
+
+            let t0 = (0.0 - origin[i]) / dir[i];
+            let t1 = (size[i] - origin[i]) / dir[i];
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+        }
+
+        if t_max - t_min < 1.0 {
+            return None;
+        }
+
+        Some(ScanLine {
+            origin: origin,
+            dir: dir,
+            t_min: t_min,
+            t_max: t_max,
+        })
+    }
 
+    // A vertical scan line down the centre of the given image column.
+    pub fn column(x: usize, height: usize) -> ScanLine {
+        ScanLine {
+            origin: na::Vector2::new(x as f32 + 0.5, 0.0),
+            dir: na::Vector2::new(0.0, 1.0),
+            t_min: 0.0,
+            t_max: height as f32,
+        }
+    }
+
+    pub fn point_at(&self, t: f32) -> na::Vector2<f32> {
+        self.origin + self.dir * t
+    }
+}
+
+// A run of black pixels along a scan line.
 pub struct Segment {
-    pub x: usize,
-    pub y_min: usize,
-    pub y_max: usize,
+    // The image points at which the scan line enters and leaves the run.
+    pub start: na::Vector2<f32>,
+    pub stop: na::Vector2<f32>,
 }
 
 // impl Segment {
@@ -19,27 +84,34 @@ pub struct Segment {
 //     }
 // }
 
-// Scans across an image, classifying pixels as either white or black, and returning a sequence of
-// black line segments.
+// Scans along a line through an image, classifying pixels as either white or black (using a
+// Binarizer), and returning a sequence of black line segments.
+// Pixels are sampled at unit steps along the line, starting half a step from where it enters the
+// image (so a column scan line samples each pixel in the column once).
 pub struct SegmentScanner<'a, I : 'a + Image> {
     image : &'a I,
-    curr_point : [usize; 2],
+    binarizer : &'a Binarizer,
+    line : ScanLine,
+    curr_t : f32,
 
     // Is the current point white?
     is_white : bool,
-    last_white_point: [usize; 2],
-    curr_pixel: image::Pixel,
+    last_white_t: f32,
+    curr_luma: u8,
 }
 
 impl<'a, I : Image> SegmentScanner<'a, I>
     where I : Image {
-    pub fn new(image : &'a I, start_point : [usize; 2]) -> SegmentScanner<I> {
+    pub fn new(image : &'a I, binarizer : &'a Binarizer, line : ScanLine) -> SegmentScanner<'a, I> {
+        let start_t = line.t_min + 0.5;
         SegmentScanner {
             image: image,
-            curr_point: start_point,
+            binarizer: binarizer,
+            line: line,
+            curr_t: start_t,
             is_white: true,
-            last_white_point: start_point,
-            curr_pixel: image.index(start_point[0], start_point[1]),
+            last_white_t: start_t,
+            curr_luma: image.sample_point_luma(line.point_at(start_t)),
         }
     }
 }
@@ -50,48 +122,68 @@ impl<'a, I : Image> Iterator for SegmentScanner<'a, I> {
     fn next(&mut self) -> Option<Segment> {
 
         loop {
-            let next_point = [self.curr_point[0], self.curr_point[1] + 1];
-            if !self.image.contains(next_point[0], next_point[1]) {
+            let next_t = self.curr_t + 1.0;
+            if next_t >= self.line.t_max {
                 return None;
             }
 
-            let next_pixel = self.image.index(next_point[0], next_point[1]);
+            let next_point = self.line.point_at(next_t);
+            let next_luma = self.image.sample_point_luma(next_point);
+            let threshold = self.binarizer.threshold_at(next_point);
 
-            if self.is_white && next_pixel.y < 128 {
+            if self.is_white && (next_luma as f32) < threshold {
                 self.is_white = false;
-                self.last_white_point = self.curr_point;
+                self.last_white_t = self.curr_t;
             }
 
-            self.curr_point = next_point;
-            self.curr_pixel = next_pixel;
+            self.curr_t = next_t;
+            self.curr_luma = next_luma;
 
-            if !self.is_white && next_pixel.y > 128 {
+            if !self.is_white && (next_luma as f32) > threshold {
                 self.is_white = true;
-                // self.curr_cross.add(self.last_white_point, next_point);
 
+                // The segment runs from the edge of the first black pixel to the edge of the last:
                 return Some(Segment {
-                    x: next_point[0],
-                    y_min: self.last_white_point[1] + 1,
-                    y_max: next_point[1] - 1,
+                    start: self.line.point_at(self.last_white_t + 0.5),
+                    stop: self.line.point_at(next_t - 0.5),
                 })
-
-                // if self.curr_cross.is_complete() {
-                //     let complete_cross = self.curr_cross.clone();
-                //     self.curr_cross = Segment::empty();
-                //     return Some(complete_cross);
-                // }
             }
         }
     }
 }
 
-pub fn scan_entire_image<I : Image>(image: &I, num_lines: usize) -> Vec<Segment> {
+// Returns parallel scan lines in the given direction, spaced so that num_lines lines cross the
+// image. Lines in direction (0, 1) are the centres of evenly spaced image columns.
+pub fn parallel_scan_lines(width: usize, height: usize, dir: na::Vector2<f32>, num_lines: usize) -> Vec<ScanLine> {
+    // Lines are offset from each other along the normal to the scan direction:
+    let normal = na::Vector2::new(dir[1], -dir[0]);
+
+    let corners = [
+        na::Vector2::new(0.0, 0.0),
+        na::Vector2::new(width as f32, 0.0),
+        na::Vector2::new(0.0, height as f32),
+        na::Vector2::new(width as f32, height as f32),
+    ];
+    let offsets : Vec<f32> = corners.iter().map(|c| na::dot(c, &normal)).collect();
+    let min_offset = offsets.iter().cloned().fold(f32::INFINITY, f32::min);
+    let max_offset = offsets.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+
+    let extent = (max_offset - min_offset).floor() as usize;
+    let step = cmp::max(1, extent / cmp::max(1, num_lines));
+
+    (0..extent).filter(|i| i % step == 0).filter_map(|i| {
+        let offset = min_offset + i as f32 + 0.5;
+        ScanLine::clipped(normal * offset, dir, width, height)
+    }).collect()
+}
+
+pub fn scan_entire_image<I : Image>(image: &I, binarizer: &Binarizer, num_lines: usize) -> Vec<Segment> {
     let mut results = Vec::new();
 
     let step = cmp::max(1, image.width() / num_lines);
 
     for x in (0..image.width()).filter(|x| x % step == 0) {
-        let scanner = SegmentScanner::new(image, [x, 0]);
+        let scanner = SegmentScanner::new(image, binarizer, ScanLine::column(x, image.height()));
 
         results.extend(scanner)
     }
@@ -2,31 +2,95 @@ extern crate core;
 // use self::core::slice::Iter;
 
 // use ffmpeg_camera::image_ycbcr;
-use ffmpeg_camera::image::Image;
+use ffmpeg_camera::image::{Image, SampleMode, BorderPolicy};
 use nalgebra as na;
+use nalgebra::Norm;
 use detection::scanning::segment;
 use std::collections::LinkedList;
 use std::cmp;
+use std::f32;
 use geometry as gm;
-
-// Represents a the intersection of a straight line with a set of 5 staff lines.
-// Stores the coordinates of each of the 5 line intersections in image coordinates.
+use ffmpeg_camera::image_pyramid::ImagePyramid;
+use ffmpeg_camera::image_view::{Region, SubImage};
+use ffmpeg_camera::ffmpeg_utils::FfmpegError;
+use detection::binarisation::{Binarizer, Binarisation};
+use detection::runs::histogram::StaffMetrics;
+use utility::thread_pool::ThreadPool;
+
+// Scan lines find staves within about 35 degrees of perpendicular to them, so spans along a scan
+// line may be up to about 1 / cos(35) times wider than the lines and spaces they cross.
+const MAX_SCAN_STRETCH : f32 = 1.25;
+
+// Crosses found at several pyramid levels are merged if their centres are closer than this
+// fraction of their space width, and their scan lines are within about 10 degrees of each other.
+const DUPLICATE_CENTRE_TOLERANCE : f32 = 0.5;
+const MIN_DUPLICATE_SCAN_DIR_DOT : f32 = 0.985;
+
+// Represents a the intersection of a straight line with a set of staff lines (usually 5).
+// Stores the coordinates of each of the line intersections in image coordinates.
 #[derive(Debug, Clone)]
 pub struct StaffCross {
-    // Note: Currently, to keep things simple, we only scan downward, so we store each span of ink
-    // as the starting and ending y-coordinate. (later we'll either store start+stop points, or add
-    // a transform from SE(2) to represent the scan coorinate system)
+    // The points at which the scan line enters and leaves each span of ink.
+    spans : Vec<[na::Vector2<f32>; 2]>,
+
+    // The direction of the scan line that found the cross (a unit vector).
+    scan_dir : na::Vector2<f32>,
+
+    // Whether each line was observed directly, or inferred from the others (e.g. where the scan
+    // line passes through a notehead, or a line is missing).
+    observed : Vec<bool>,
+
+    // How closely the cross resembles a staff cross-section, in [0, 1] (see plausibility).
+    score : f32,
+
+    // The centre, refined to sub-pixel accuracy from the image intensities (see refine_centre).
+    refined_centre : Option<na::Vector2<f32>>,
+}
 
-    pub x : usize,
-    spans : Vec<[usize; 2]>,
+// Controls how strictly crosses are judged to be plausible.
+// Each span's line length and gap length may differ from the cross's averages by up to the given
+// relative errors. Very small spans suffer more from quantisation, so have their own limits.
+#[derive(Debug, Clone, Copy)]
+pub struct PlausibilityParams {
+    pub max_gap_error: f32,
+    pub max_len_error: f32,
+
+    // Limits used for averages below small_width pixels.
+    pub small_width: f32,
+    pub max_small_gap_error: f32,
+    pub max_small_len_error: f32,
+
+    // Line widths accepted for the single span of a one-line cross, when the page's line width
+    // is unknown. Otherwise any isolated black run (e.g. text, stems, or noteheads) would be
+    // accepted.
+    pub min_single_line_width: f32,
+    pub max_single_line_width: f32,
+}
+
+impl Default for PlausibilityParams {
+    fn default() -> PlausibilityParams {
+        PlausibilityParams {
+            max_gap_error: 0.33,
+            max_len_error: 0.33,
+            small_width: 3.0,
+            max_small_gap_error: 0.75,
+            max_small_len_error: 2.5,
+            min_single_line_width: 1.0,
+            max_single_line_width: 5.0,
+        }
+    }
 }
 
 impl StaffCross {
 
-    fn empty() -> StaffCross {
+    fn empty(scan_dir: na::Vector2<f32>) -> StaffCross {
         StaffCross {
-            x: 0,
             spans: Vec::new(),
+            scan_dir: scan_dir,
+            observed: Vec::new(),
+            // Note: Crosses are fully weighted until they are scored.
+            score: 1.0,
+            refined_centre: None,
         }
     }
 
@@ -38,16 +102,43 @@ impl StaffCross {
     //     na::Vector2::new(avg_x, avg_y)
     // }
 
+    // Builds a cross from a sequence of consecutive segments along a scan line.
+    fn from_segments(segments: &[segment::Segment], scan_dir: na::Vector2<f32>) -> StaffCross {
+        let mut staff_cross = StaffCross::empty(scan_dir);
+        for segment in segments {
+            staff_cross.add_segment(segment);
+        }
+        staff_cross
+    }
+
+    pub fn num_lines(&self) -> usize {
+        self.spans.len()
+    }
+
+    // The centre of the middle line (or of the middle space, for an even number of lines).
+    // Note: Returns the refined centre, if it has been refined.
     pub fn centre(&self) -> na::Vector2<f32> {
-        let mid_span = self.spans[2];
+        if let Some(centre) = self.refined_centre {
+            return centre;
+        }
 
-        let avg_y = (mid_span[0] + mid_span[1]) as f32 / 2.0;
-        let avg_x = self.x as f32;
+        self.edge_centre()
+    }
 
-        na::Vector2::new(avg_x, avg_y)
+    // The centre, as measured from the edges of the spans.
+    pub fn edge_centre(&self) -> na::Vector2<f32> {
+        let n = self.spans.len();
+        let first_mid_span = self.spans[(n - 1) / 2];
+        let last_mid_span = self.spans[n / 2];
+
+        (first_mid_span[0] + last_mid_span[1]) / 2.0
     }
 
+    // Note: A single line has no spaces, so its average space width is 0.
     pub fn average_space_width(&self, line: &gm::Line) -> f32 {
+        if self.spans.len() < 2 {
+            return 0.0;
+        }
 
         // println!("self.spans: {:?}", self.spans);
         // println!("self.span_points(): {:?}", self.span_points());
@@ -70,7 +161,7 @@ impl StaffCross {
 
         // println!("sum: {:?}", sum);
 
-        let avg = sum / 4.0;
+        let avg = sum / spaces.len() as f32;
 
         // println!("avg: {:?}", avg);
 
@@ -86,109 +177,281 @@ impl StaffCross {
             .map(|w| (w[1] - w[0]).abs());
 
         let sum : f32 = line_widths.fold(0.0, |a, b| a + b);
-        let avg = sum / 5.0;
+        let avg = sum / self.spans.len() as f32;
 
         avg
     }
 
     fn add_segment(&mut self, segment: &segment::Segment) {
-        self.x = segment.x;
-        self.spans.push([segment.y_min, segment.y_max])
+        self.spans.push([segment.start, segment.stop]);
+        self.observed.push(true);
     }
 
-    // fn is_complete(&self) -> bool {
-    //     self.spans.len() >= 5
-    // }
+    fn add_inferred_span(&mut self, start: na::Vector2<f32>, stop: na::Vector2<f32>) {
+        self.spans.push([start, stop]);
+        self.observed.push(false);
+    }
+
+    pub fn is_observed(&self, line_index: usize) -> bool {
+        self.observed[line_index]
+    }
+
+    pub fn num_observed(&self) -> usize {
+        self.observed.iter().filter(|&&o| o).count()
+    }
+
+    // Returns true if every line of the cross was observed.
+    pub fn is_complete(&self) -> bool {
+        self.num_observed() == self.spans.len()
+    }
+
+    // Returns the cross mapped from a downsampled image into an image that is larger by the given
+    // x and y scales (see ImagePyramid::level_scale).
+    pub fn scaled(&self, scale: na::Vector2<f32>) -> StaffCross {
+        let scale_point = |pt: na::Vector2<f32>| na::Vector2::new(pt[0] * scale[0], pt[1] * scale[1]);
+        StaffCross {
+            spans: self.spans.iter().map(|span| [scale_point(span[0]), scale_point(span[1])]).collect(),
+            scan_dir: na::normalize(&scale_point(self.scan_dir)),
+            observed: self.observed.clone(),
+            score: self.score,
+            refined_centre: self.refined_centre.map(|c| scale_point(c)),
+        }
+    }
+
+    // Returns the cross moved by the given offset (e.g. from a SubImage to its parent).
+    pub fn translated(&self, offset: na::Vector2<f32>) -> StaffCross {
+        StaffCross {
+            spans: self.spans.iter().map(|span| [span[0] + offset, span[1] + offset]).collect(),
+            scan_dir: self.scan_dir,
+            observed: self.observed.clone(),
+            score: self.score,
+            refined_centre: self.refined_centre.map(|c| c + offset),
+        }
+    }
+
+    // The average space width along the scan line.
+    // Note: A single line has no spaces, so its line width is used instead.
+    fn scan_space_width(&self) -> f32 {
+        if self.spans.len() < 2 {
+            return na::dot(&(self.spans[0][1] - self.spans[0][0]), &self.scan_dir).max(1.0);
+        }
+
+        let sum = self.spans.windows(2)
+            .fold(0.0, |sum, w| sum + na::dot(&(w[1][0] - w[0][1]), &self.scan_dir));
+        sum / (self.spans.len() - 1) as f32
+    }
+
+    // Returns whether both crosses are cross-sections of the same staff at the same place (e.g.
+    // when found at several pyramid levels).
+    fn is_duplicate_of(&self, other: &StaffCross) -> bool {
+        if self.num_lines() != other.num_lines() ||
+            na::dot(&self.scan_dir, &other.scan_dir).abs() < MIN_DUPLICATE_SCAN_DIR_DOT {
+            return false;
+        }
+
+        let tolerance = DUPLICATE_CENTRE_TOLERANCE * self.scan_space_width().min(other.scan_space_width());
+        (self.centre() - other.centre()).norm() <= tolerance
+    }
 
-    pub fn spans(&self) -> self::core::slice::Iter<[usize; 2]> {
+    pub fn spans(&self) -> self::core::slice::Iter<[na::Vector2<f32>; 2]> {
         self.spans.iter()
     }
 
     pub fn span_points(&self) -> Vec<[na::Vector2<f32>; 2]> {
-        let to_vec = |span: &[usize; 2]| -> [na::Vector2<f32>; 2] {
-            let p1 = na::Vector2::new(self.x as f32 + 0.5, span[0] as f32 - 0.5);
-            let p2 = na::Vector2::new(self.x as f32 + 0.5, span[1] as f32 + 0.5);
-            [p1, p2]
-        };
-        self.spans.iter().map(to_vec).collect()
+        self.spans.clone()
     }
 
     pub fn scan_direction(&self) -> na::Vector2<f32> {
-        // let p1 = na::Vector2::new(self.x as f32, self.spans[0][1] as f32 - 0.5);
-        // let p2 = na::Vector2::new(self.x as f32, self.spans[4][1] as f32 + 0.5);
-        //
-        // let diff = p2 - p1;
-        // let dir = na::normalize(&diff);
-        //
-        // dir
+        self.scan_dir
+    }
 
-        na::Vector2::new(0.0, 1.0)
+    // The score assigned when the cross was found, in [0, 1]. Used to weight crosses when fitting.
+    pub fn score(&self) -> f32 {
+        self.score
     }
 
     // Returns whether the sequence of spans is regular enough that it could plausibly be a
     // cross-section of a staff.
     pub fn is_plausible(&self) -> bool {
-        let mut len_sum = 0;
-        let mut gap_sum = 0;
-        let mut last_end = 0;
-        for span in self.spans() {
-            if last_end > 0 {
-                gap_sum += span[0] - last_end;
+        self.is_plausible_with(None)
+    }
+
+    // As is_plausible, but also rejects crosses whose line and space widths are inconsistent with
+    // the staff metrics of the page (if given).
+    pub fn is_plausible_with(&self, metrics: Option<&StaffMetrics>) -> bool {
+        self.plausibility(metrics, &PlausibilityParams::default()) > 0.0
+    }
+
+    // Returns how regular the sequence of spans is, from 0 (implausible as a staff cross-section)
+    // to 1 (perfectly even line and gap lengths).
+    // Each span's relative errors are scaled by the maximum errors allowed by params, and the
+    // score is the geometric mean of the remaining margins (see cross_score).
+    // Note: A single span has no spacing to check, so is scored by how closely its length matches
+    // the page's line width (if known).
+    pub fn plausibility(&self, metrics: Option<&StaffMetrics>, params: &PlausibilityParams) -> f32 {
+        // Positions of the span ends along the scan line.
+        // Note: Spans are measured between the centres of their first and last pixels, as the
+        // default limits were chosen for that measure.
+        let origin = self.spans[0][0];
+        let span_times : Vec<[f32; 2]> = self.spans().map(|span| {
+            let t0 = na::dot(&(span[0] - origin), &self.scan_dir) + 0.5;
+            let t1 = na::dot(&(span[1] - origin), &self.scan_dir) - 0.5;
+            [t0, t1]
+        }).collect();
+
+        let mut len_sum = 0.0;
+        let mut gap_sum = 0.0;
+        for (i, span) in span_times.iter().enumerate() {
+            if i > 0 {
+                gap_sum += span[0] - span_times[i - 1][1];
             }
 
             len_sum += span[1] - span[0];
-
-            last_end = span[1];
         }
 
-        let avg_gap = gap_sum as f32 / 4.0;
-        let avg_len = len_sum as f32 / 5.0;
+        let num_gaps = span_times.len() - 1;
+        let avg_gap = if num_gaps > 0 { gap_sum / num_gaps as f32 } else { 0.0 };
+        let avg_len = len_sum / span_times.len() as f32;
 
         // Allow some flexibility when detections are close to the smallest possible:
-        let max_gap_error = if avg_gap < 3.0 { 0.75 } else { 0.33 };
-        let max_len_error = if avg_len < 3.0 { 2.5 } else { 0.33 };
+        let max_gap_error = if avg_gap < params.small_width { params.max_small_gap_error } else { params.max_gap_error };
+        let max_len_error = if avg_len < params.small_width { params.max_small_len_error } else { params.max_len_error };
 
         // If stafflines are thicker than the spaces between them.
-        if avg_len > avg_gap {
-            return false;
+        if num_gaps > 0 && avg_len > avg_gap {
+            return 0.0;
+        }
+
+        // If a single span is not the width of a staff line.
+        // Note: The span is checked against the page's line width below if it is known.
+        let has_line_width = metrics.map_or(false, |metrics| metrics.line_width.is_confident());
+        if num_gaps == 0 && !has_line_width {
+            let width = avg_len + 1.0;
+            if width < params.min_single_line_width || width > params.max_single_line_width * MAX_SCAN_STRETCH {
+                return 0.0;
+            }
         }
 
-        let mut last_end = 0;
-        for span in self.spans() {
-            if last_end > 0 {
-                let gap_len = span[0] - last_end;
-                let gap_rel_err = (gap_len as f32 - avg_gap).abs() / avg_gap;
+        // If the widths are far from those of the page's staves.
+        // Note: Metrics are measured between pixel edges, so the centre-based measures are
+        // converted back.
+        if let Some(metrics) = metrics {
+            if !metrics.line_width.accepts(avg_len + 1.0, MAX_SCAN_STRETCH) {
+                return 0.0;
+            }
+            if num_gaps > 0 && !metrics.space_width.accepts(avg_gap - 1.0, MAX_SCAN_STRETCH) {
+                return 0.0;
+            }
+        }
+
+        // Note: Spans are measured between pixel centres, so 1px lines have zero length. Relative
+        // errors are taken to be zero when the averages are, as all of the spans then agree.
+        let mut margins = Vec::with_capacity(span_times.len() * 2);
+        for (i, span) in span_times.iter().enumerate() {
+            if i > 0 {
+                let gap_len = span[0] - span_times[i - 1][1];
+                let gap_rel_err = if avg_gap > 0.0 { (gap_len - avg_gap).abs() / avg_gap } else { 0.0 };
                 if gap_rel_err > max_gap_error {
-                    return false;
+                    return 0.0;
                 }
+                margins.push(1.0 - gap_rel_err / max_gap_error);
             }
 
             let curr_len = span[1] - span[0];
-            let len_rel_err = (curr_len as f32 - avg_len).abs() / avg_len;
+            let len_rel_err = if avg_len > 0.0 { (curr_len - avg_len).abs() / avg_len } else { 0.0 };
             if len_rel_err > max_len_error {
-                return false;
+                return 0.0;
             }
+            margins.push(1.0 - len_rel_err / max_len_error);
+        }
 
-            last_end = span[1];
+        if num_gaps == 0 {
+            if let Some(metrics) = metrics {
+                if metrics.line_width.is_confident() {
+                    let expected = metrics.line_width.value;
+                    let len_rel_err = (avg_len + 1.0 - expected).abs() / expected;
+                    margins.push((1.0 - len_rel_err / max_len_error).max(0.0));
+                }
+            }
         }
 
-        true
+        cross_score(&margins, span_times.len(), span_times.len())
+    }
+
+    // Refines the centre of the cross to sub-pixel accuracy.
+    // The centre of each observed line is estimated as the darkness-weighted mean position along
+    // the scan line, and the centre of the cross is found by fitting evenly spaced lines to them.
+    // Note: The edge centre is kept if fewer than two lines were observed.
+    pub fn refine_centre<I: Image>(&mut self, image: &I) {
+        let origin = self.spans[0][0];
+        let n = self.spans.len();
+        let mid_index = (n as f32 - 1.0) / 2.0;
+
+        // Line index (relative to the middle line) and refined position of each observed line:
+        let mut line_times = Vec::new();
+        for (i, span) in self.spans.iter().enumerate() {
+            if !self.observed[i] {
+                continue;
+            }
+
+            let t0 = na::dot(&(span[0] - origin), &self.scan_dir);
+            let t1 = na::dot(&(span[1] - origin), &self.scan_dir);
+
+            // Sample a pixel beyond each edge, so that partially covered edge pixels are included:
+            let step = 0.25;
+            let mut weight_sum = 0.0;
+            let mut time_sum = 0.0;
+            let mut t = t0 - 1.0;
+            while t <= t1 + 1.0 {
+                let pt = origin + self.scan_dir * t;
+                if let Some(luma) = image.sample_luma(pt, SampleMode::Bilinear, BorderPolicy::Clamp) {
+                    let darkness = 255.0 - luma;
+                    weight_sum += darkness;
+                    time_sum += darkness * t;
+                }
+                t += step;
+            }
+
+            let line_t = if weight_sum > 0.0 { time_sum / weight_sum } else { (t0 + t1) / 2.0 };
+            line_times.push((i as f32 - mid_index, line_t));
+        }
+
+        if line_times.len() < 2 {
+            return;
+        }
+
+        // Least squares fit of line_t = centre_t + period * index:
+        let count = line_times.len() as f32;
+        let mean_index = line_times.iter().fold(0.0, |sum, &(k, _)| sum + k) / count;
+        let mean_t = line_times.iter().fold(0.0, |sum, &(_, t)| sum + t) / count;
+        let cov = line_times.iter().fold(0.0, |sum, &(k, t)| sum + (k - mean_index) * (t - mean_t));
+        let var = line_times.iter().fold(0.0, |sum, &(k, _)| sum + (k - mean_index) * (k - mean_index));
+        let period = cov / var;
+        let centre_t = mean_t - period * mean_index;
+
+        // Keep the centre on the scan line, where the edge centre lies:
+        self.refined_centre = Some(origin + self.scan_dir * centre_t);
     }
 }
 
-// Scans across an image, returning a sequence of detected StaffPoints.
+// Scans across an image, returning a sequence of detected StaffPoints, each with the given number
+// of lines.
 pub struct StaffScanner<'a, I : 'a + Image> {
     // image: &'a image_ycbcr::Image,
     segment_scanner: segment::SegmentScanner<'a, I>,
     segment_queue: LinkedList<segment::Segment>,
+    scan_dir: na::Vector2<f32>,
+    num_lines: usize,
 }
 
 impl<'a, I : Image> StaffScanner<'a, I> {
-    pub fn new(image : &'a I, start_point : [usize; 2]) -> StaffScanner<I> {
+    pub fn new(image : &'a I, binarizer : &'a Binarizer, line : segment::ScanLine, num_lines : usize) -> StaffScanner<'a, I> {
         StaffScanner {
             // image: image,
-            segment_scanner: segment::SegmentScanner::new(image, start_point),
+            segment_scanner: segment::SegmentScanner::new(image, binarizer, line),
             segment_queue: LinkedList::new(),
+            scan_dir: line.dir,
+            num_lines: num_lines,
         }
     }
 }
@@ -202,12 +465,12 @@ impl<'a, I : Image> Iterator for StaffScanner<'a, I> {
             let maybe_segment = self.segment_scanner.next();
             if let Some(segment) = maybe_segment {
                 self.segment_queue.push_back(segment);
-                if self.segment_queue.len() > 5 {
+                if self.segment_queue.len() > self.num_lines {
                     self.segment_queue.pop_front();
                 }
 
-                if self.segment_queue.len() == 5 {
-                    let mut staff_cross = StaffCross::empty();
+                if self.segment_queue.len() == self.num_lines {
+                    let mut staff_cross = StaffCross::empty(self.scan_dir);
                     for segment in &self.segment_queue {
                         staff_cross.add_segment(&segment);
                     }
@@ -221,18 +484,366 @@ impl<'a, I : Image> Iterator for StaffScanner<'a, I> {
     }
 }
 
-pub fn scan_entire_image<I : Image>(image: &I, num_lines: usize) -> Vec<StaffCross> {
+// Controls how an image is scanned for staff crosses.
+#[derive(Debug, Clone)]
+pub struct ScanParams {
+    // Numbers of staff lines to look for (usually just 5).
+    pub line_counts: Vec<usize>,
+
+    // Scan line angles (see scan_angles), and number of scan lines per angle.
+    pub angles: Vec<f32>,
+    pub num_scan_lines: usize,
+
+    // Allow crosses in which up to this many lines are covered by thicker segments (e.g. noteheads,
+    // beams, or lines fused together by a symbol).
+    pub max_occluded_lines: usize,
+
+    // Allow crosses in which up to this many lines are missing.
+    pub max_missing_lines: usize,
+
+    // Allow crosses in which up to this many lines are inferred in total (occluded or missing).
+    // Note: Each inferred line weakens the evidence for a cross, and with several inferred lines
+    // any two similar runs (e.g. beams) would be accepted.
+    pub max_inferred_lines: usize,
+
+    // The staff line and space widths of the page (see runs::histogram::staff_metrics), used to
+    // reject implausible crosses. Crosses are not checked against metrics if None.
+    pub staff_metrics: Option<StaffMetrics>,
+
+    // How strictly crosses of consecutive segments are judged (see StaffCross::plausibility).
+    pub plausibility: PlausibilityParams,
+
+    // Whether to refine the centre of each cross to sub-pixel accuracy.
+    pub refine_centres: bool,
+
+    // Scan lines are scanned in parallel with this.
+    pub thread_pool: ThreadPool,
+}
+
+impl Default for ScanParams {
+    fn default() -> ScanParams {
+        ScanParams {
+            line_counts: vec![5],
+            angles: scan_angles(3),
+            num_scan_lines: 320,
+            max_occluded_lines: 1,
+            max_missing_lines: 1,
+            max_inferred_lines: 1,
+            staff_metrics: None,
+            plausibility: PlausibilityParams::default(),
+            refine_centres: true,
+            thread_pool: ThreadPool::default(),
+        }
+    }
+}
+
+// Combines the margins by which a cross's measurements were within their limits (each in [0, 1])
+// into a single score, so that crosses from both scanners can be ranked together.
+// The score is the geometric mean of the margins, scaled by the fraction of the lines that were
+// observed. A perfectly regular, fully observed cross scores 1.
+// Note: A margin of exactly 0 (an error equal to its limit) would otherwise score the cross as
+// implausible, so scores are clamped to be positive.
+fn cross_score(margins: &[f32], num_observed: usize, num_lines: usize) -> f32 {
+    if margins.is_empty() || num_lines == 0 || margins.iter().any(|&m| !m.is_finite() || m <= 0.0) {
+        return f32::EPSILON;
+    }
+
+    let log_sum = margins.iter().fold(0.0, |sum, m| sum + m.ln());
+    let mean = (log_sum / margins.len() as f32).exp();
+
+    (mean * num_observed as f32 / num_lines as f32).max(f32::EPSILON)
+}
+
+// A segment's extent along a scan line.
+#[derive(Clone, Copy)]
+struct SegmentTimes {
+    t0: f32,
+    t1: f32,
+}
+
+impl SegmentTimes {
+    fn width(&self) -> f32 {
+        self.t1 - self.t0
+    }
+}
+
+// Attempts to fit a staff with num_lines lines to the segments along a scan line, given that
+// segments i and i + 1 are lines k and k + 1 of the staff.
+// Lines that are covered by thicker segments or missing are inferred from the line width and
+// spacing of the two given segments. Segments lying within the spaces are ignored.
+fn fit_tolerant_cross(
+    line: &segment::ScanLine,
+    segments: &[segment::Segment],
+    times: &[SegmentTimes],
+    i: usize,
+    k: usize,
+    num_lines: usize,
+    params: &ScanParams) -> Option<(StaffCross, f32, f32)> {
+
+    let line_width = (times[i].width() + times[i + 1].width()) / 2.0;
+    let period = times[i + 1].t0 - times[i].t0;
+
+    // Stafflines must be thinner than the spaces between them.
+    if period < line_width * 2.0 {
+        return None;
+    }
+
+    let width_tolerance = (line_width * 0.5).max(1.5);
+    let pos_tolerance = (period * 0.25).max(1.5);
+    if (times[i].width() - times[i + 1].width()).abs() > width_tolerance {
+        return None;
+    }
+
+    let first_t = times[i].t0 - k as f32 * period;
+    let last_t = first_t + (num_lines - 1) as f32 * period + line_width;
+    if first_t < line.t_min || last_t > line.t_max {
+        return None;
+    }
+
+    let mut cross = StaffCross::empty(line.dir);
+    let mut num_occluded = 0;
+    let mut num_missing = 0;
+
+    // Observed lines score by how closely they fit the staff (see cross_score):
+    let mut margins = Vec::with_capacity(num_lines);
+    for j in 0..num_lines {
+        let t = first_t + j as f32 * period;
+        let centre = t + line_width / 2.0;
+
+        let covering = times.iter().position(|s| s.t0 <= centre && centre <= s.t1);
+        match covering {
+            Some(c) if (times[c].width() - line_width).abs() <= width_tolerance &&
+                (times[c].t0 - t).abs() <= pos_tolerance => {
+                let width_error = (times[c].width() - line_width).abs() / width_tolerance;
+                let pos_error = (times[c].t0 - t).abs() / pos_tolerance;
+                margins.push(1.0 - width_error.max(pos_error));
+                cross.add_segment(&segments[c]);
+            },
+            Some(c) if times[c].width() > line_width => {
+                num_occluded += 1;
+                cross.add_inferred_span(line.point_at(t), line.point_at(t + line_width));
+            },
+            _ => {
+                num_missing += 1;
+                cross.add_inferred_span(line.point_at(t), line.point_at(t + line_width));
+            },
+        }
+    }
+
+    if num_occluded > params.max_occluded_lines || num_missing > params.max_missing_lines ||
+        num_occluded + num_missing > params.max_inferred_lines {
+        return None;
+    }
+
+    if let Some(ref metrics) = params.staff_metrics {
+        if !metrics.line_width.accepts(line_width, MAX_SCAN_STRETCH) ||
+            !metrics.space_width.accepts(period - line_width, MAX_SCAN_STRETCH) {
+            return None;
+        }
+    }
+
+    // The observed lines must be as regular as those of an exact cross.
+    // Note: Inferred spans match the fitted staff exactly, so only the observed spans can fail.
+    if cross.plausibility(params.staff_metrics.as_ref(), &params.plausibility) <= 0.0 {
+        return None;
+    }
+
+    cross.score = cross_score(&margins, margins.len(), num_lines);
+
+    Some((cross, first_t, last_t))
+}
+
+// Returns the plausible crosses along a scan line for each of the given line counts.
+// When looking for several line counts, windows that lie within a plausible window with more
+// lines are ignored (e.g. the lower 4 lines of a 5 line staff are not also reported as a 4 line
+// staff).
+// Crosses of consecutive, regular segments are found first. Where symbols cover or interrupt the
+// staff lines, crosses with some inferred lines are then found (see ScanParams).
+pub fn scan_line_crosses<I : Image>(image: &I, binarizer: &Binarizer, line: segment::ScanLine, params: &ScanParams) -> Vec<StaffCross> {
+    let segments : Vec<segment::Segment> = segment::SegmentScanner::new(image, binarizer, line).collect();
+
+    let mut counts : Vec<usize> = params.line_counts.iter().cloned().filter(|&n| n > 0).collect();
+    counts.sort();
+    counts.dedup();
+
+    let mut results = Vec::new();
+    let mut accepted_windows : Vec<(usize, usize)> = Vec::new();
+    for &n in counts.iter().rev() {
+        if segments.len() < n {
+            continue;
+        }
+
+        for start in 0..(segments.len() - n + 1) {
+            let end = start + n;
+            if accepted_windows.iter().any(|&(s, e)| s <= start && end <= e) {
+                continue;
+            }
+
+            let mut cross = StaffCross::from_segments(&segments[start..end], line.dir);
+            let score = cross.plausibility(params.staff_metrics.as_ref(), &params.plausibility);
+            if score > 0.0 {
+                cross.score = score;
+                if params.refine_centres {
+                    cross.refine_centre(image);
+                }
+
+                accepted_windows.push((start, end));
+                results.push(cross);
+            }
+        }
+    }
+
+    if params.max_inferred_lines == 0 || (params.max_occluded_lines == 0 && params.max_missing_lines == 0) {
+        return results;
+    }
+
+    let times : Vec<SegmentTimes> = segments.iter().map(|s| {
+        SegmentTimes {
+            t0: na::dot(&(s.start - line.origin), &line.dir),
+            t1: na::dot(&(s.stop - line.origin), &line.dir),
+        }
+    }).collect();
+
+    // Ranges along the scan line that are already explained by a cross:
+    let mut covered : Vec<(f32, f32)> = accepted_windows.iter()
+        .map(|&(start, end)| (times[start].t0, times[end - 1].t1))
+        .collect();
+
+    let mut candidates = Vec::new();
+    for &n in counts.iter().rev() {
+        if n < 2 || segments.len() < 2 {
+            continue;
+        }
+
+        for i in 0..(segments.len() - 1) {
+            for k in 0..(n - 1) {
+                if let Some(candidate) = fit_tolerant_cross(&line, &segments, &times, i, k, n, params) {
+                    candidates.push(candidate);
+                }
+            }
+        }
+    }
+
+    // Prefer the candidates with the most observed lines:
+    candidates.sort_by(|a, b| b.0.num_observed().cmp(&a.0.num_observed()));
+    for (mut cross, t0, t1) in candidates {
+        if covered.iter().any(|&(c0, c1)| t0 < c1 && c0 < t1) {
+            continue;
+        }
+
+        if params.refine_centres {
+            cross.refine_centre(image);
+        }
+
+        covered.push((t0, t1));
+        results.push(cross);
+    }
+
+    results
+}
+
+pub fn scan_entire_image<I : Image + Sync>(image: &I, binarizer: &Binarizer, params: &ScanParams) -> Vec<StaffCross> {
+    let step = cmp::max(1, image.width() / params.num_scan_lines);
+
+    let lines : Vec<segment::ScanLine> = (0..image.width())
+        .filter(|x| x % step == 0)
+        .map(|x| segment::ScanLine::column(x, image.height()))
+        .collect();
+
+    scan_lines(image, binarizer, &lines, params)
+}
+
+// Scans each of the given lines, returning the crosses in the order of the lines.
+// Note: Lines are scanned in parallel, but the results do not depend on the number of threads.
+pub fn scan_lines<I : Image + Sync>(image: &I, binarizer: &Binarizer, lines: &[segment::ScanLine], params: &ScanParams) -> Vec<StaffCross> {
+    let line_results = params.thread_pool.map(lines, |line| {
+        scan_line_crosses(image, binarizer, *line, params)
+    });
+
     let mut results = Vec::new();
+    for crosses in line_results {
+        results.extend(crosses);
+    }
 
-    let step = cmp::max(1, image.width() / num_lines);
+    results
+}
 
-    for x in (0..image.width()).filter(|x| x % step == 0) {
-        let scanner = StaffScanner::new(image, [x, 0]);
+// Returns num_angles scan angles (in radians), evenly spaced over a half turn, starting with
+// vertical scan lines.
+// Note: A scan line finds staves within about 35 degrees of perpendicular to it, so at least 3
+// angles are required to find staves at any angle.
+pub fn scan_angles(num_angles: usize) -> Vec<f32> {
+    (0..num_angles).map(|i| {
+        f32::consts::FRAC_PI_2 + f32::consts::PI * i as f32 / num_angles as f32
+    }).collect()
+}
 
-        let crosses = scanner.filter(|c| c.is_plausible());
+// Scans an image along parallel lines at each of the given angles (in radians, measured from the
+// x axis towards the y axis).
+pub fn scan_image_at_angles<I : Image + Sync>(image: &I, binarizer: &Binarizer, params: &ScanParams) -> Vec<StaffCross> {
+    let mut lines = Vec::new();
 
-        results.extend(crosses)
+    for angle in &params.angles {
+        let dir = na::Vector2::new(angle.cos(), angle.sin());
+        lines.extend(segment::parallel_scan_lines(image.width(), image.height(), dir, params.num_scan_lines));
+    }
+
+    scan_lines(image, binarizer, &lines, params)
+}
+
+// Scans every level of an image pyramid, returning the detected crosses in base image coordinates.
+// Small staves are found in the finer levels, while large, noisy staves are found in the coarser
+// levels. Crosses found at the same place in several levels are merged (see
+// merge_duplicate_crosses).
+// Note: Each level is binarised separately, as local thresholds depend on the image scale.
+pub fn scan_image_pyramid<I : Image + Sync>(pyramid: &ImagePyramid<I>, binarisation: &Binarisation, params: &ScanParams) -> Vec<StaffCross> {
+    let base_binarizer = binarisation.build(pyramid.base);
+    let mut results = scan_image_at_angles(pyramid.base, &*base_binarizer, params);
+
+    for (i, level) in pyramid.levels.iter().enumerate() {
+        let scale = pyramid.level_scale(i + 1);
+        let mean_scale = (scale[0] + scale[1]) / 2.0;
+
+        // Note: Using the same number of scan lines at each level keeps the spacing between scan
+        // lines roughly the same in base image coordinates. Levels narrower than num_scan_lines
+        // pixels are scanned at every pixel, so their scan lines are further apart.
+        let level_params = ScanParams {
+            staff_metrics: params.staff_metrics.map(|m| m.scaled(1.0 / mean_scale)),
+            .. params.clone()
+        };
+        let level_binarizer = binarisation.build(level);
+        let crosses = scan_image_at_angles(level, &*level_binarizer, &level_params);
+
+        merge_duplicate_crosses(&mut results, crosses.iter().map(|c| c.scaled(scale)).collect());
     }
 
     results
 }
+
+// Adds crosses to those found so far, so that a staff found at several pyramid levels contributes
+// one cross per location (the highest scoring one) rather than one per level.
+// Note: Crosses are only compared with those found so far, as crosses found along the scan lines
+// of a single level are already distinct.
+fn merge_duplicate_crosses(results: &mut Vec<StaffCross>, crosses: Vec<StaffCross>) {
+    let num_previous = results.len();
+    for cross in crosses {
+        match results[..num_previous].iter().position(|previous| cross.is_duplicate_of(previous)) {
+            Some(i) => {
+                if cross.score > results[i].score {
+                    results[i] = cross;
+                }
+            },
+            None => results.push(cross),
+        }
+    }
+}
+
+// Scans a region of an image, returning the detected crosses in full image coordinates.
+// Returns an error if the region does not overlap the image.
+pub fn scan_region<I : Image + Sync>(image: &I, region: Region, binarisation: &Binarisation, params: &ScanParams) -> Result<Vec<StaffCross>, FfmpegError> {
+    let view = try!(SubImage::new(image, region));
+    let binarizer = binarisation.build(&view);
+    let crosses = scan_image_at_angles(&view, &*binarizer, params);
+
+    Ok(crosses.iter().map(|c| c.translated(view.region.offset())).collect())
+}
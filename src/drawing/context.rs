@@ -20,6 +20,8 @@ use detection::scanning::staff_cross::StaffCross;
 use detection::ransac::staff_cross::StaffCrossLine;
 use detection::ransac::RansacState;
 use geometry as gm;
+use geometry::curved_staff::CurvedStaff;
+use geometry::staff::StaffPath;
 
 use utility;
 
@@ -261,16 +263,17 @@ impl<'a> DrawingFrame<'a> {
         self.draw_line(target, e1, e2, lw, colour);
     }
 
-    pub fn draw_staff_from_parts(&self, target: &mut glium::Frame, p1: na::Vector2<f32>, p2: na::Vector2<f32>, sw: f32, lw: f32, colour: [f32; 4]) {
+    pub fn draw_staff_from_parts(&self, target: &mut glium::Frame, p1: na::Vector2<f32>, p2: na::Vector2<f32>, sw: f32, lw: f32, num_lines: usize, colour: [f32; 4]) {
         self.set_view_matrices();
         let dir = na::normalize(&(p2 - p1));
         let norm = na::Vector2::new(dir[1], -dir[0]);
 
         let line_space = lw + sw;
+        let mid = (num_lines as f32 - 1.0) / 2.0;
 
         // let mut lines = Vec::<gm::Line>::new();
-        for i in -2..3 {
-            let d = line_space * (i as f32);
+        for i in 0..num_lines {
+            let d = line_space * (i as f32 - mid);
 
             let e1 = p1 + norm * d;
             let e2 = p2 + norm * d;
@@ -294,28 +297,48 @@ impl<'a> DrawingFrame<'a> {
             p2,
             staff.space_width,
             staff.line_width,
+            staff.num_lines,
             colour
         )
     }
 
+    // Draws each line of the staff as a polyline following the curved centre line.
+    pub fn draw_curved_staff_in_image<I: Image>(&self, target: &mut glium::Frame, ycbcr_frame : &I, staff: &CurvedStaff, colour: [f32; 4]) {
+        self.set_view_matrices();
+        let step = 2.0;
+        let num_steps = (staff.length / step).ceil().max(1.0) as usize;
+
+        let line_space = staff.line_width + staff.space_width;
+        let mid = (staff.num_lines as f32 - 1.0) / 2.0;
+
+        let mut lines = Vec::<gm::Line>::new();
+        for i in 0..staff.num_lines {
+            let d = line_space * (i as f32 - mid);
+            let point_at = |t: f32| {
+                let pt = staff.point_at_time(t) + staff.normal_at_time(t) * d;
+                ycbcr_frame.opengl_coords_for_point(pt)
+            };
+
+            let mut prev = point_at(0.0);
+            for j in 1..num_steps + 1 {
+                let t = (j as f32 * step).min(staff.length);
+                let next = point_at(t);
+                lines.push(gm::Line::new(prev, next));
+                prev = next;
+            }
+        }
+
+        self.draw_lines(target, &lines, staff.line_width, colour);
+    }
+
     pub fn draw_staff_cross(&self, mut target: &mut glium::Frame, ycbcr_frame : &image_ycbcr::Image, cross: &StaffCross, colour: [f32; 4]) {
         self.set_view_matrices();
         let pix_h = 1.0; // 2.0 * (1.0 / ycbcr_frame.height as f32);
         let scan_draw_cols = 1.0;
 
-        let x = cross.x;
         for span in cross.spans() {
-            let mut p1 = ycbcr_frame.opengl_coords_for_index([x, span[0]]);
-            let mut p2 = ycbcr_frame.opengl_coords_for_index([x, span[1]]);
-
-            // Draw from the top of the first pixel to the bottom of the second:
-            if p2[1] < p1[1] {
-                    p1[1] += pix_h / 2.0;
-                    p2[1] -= pix_h / 2.0;
-                } else {
-                    p1[1] -= pix_h / 2.0;
-                    p2[1] += pix_h / 2.0;
-                }
+            let p1 = ycbcr_frame.opengl_coords_for_point(span[0]);
+            let p2 = ycbcr_frame.opengl_coords_for_point(span[1]);
 
             self.draw_line(&mut target, p1, p2, pix_h * scan_draw_cols, colour);
             // self.draw_line(&mut target, p1, p2, (p2[1] - p1[1]).abs(), colour);
@@ -324,31 +347,30 @@ impl<'a> DrawingFrame<'a> {
 
     pub fn draw_staff_crosses<I: Image>(&self, mut target: &mut glium::Frame, ycbcr_frame : &I, crosses: &[StaffCross], colour: [f32; 4]) {
         self.set_view_matrices();
-        let pix_h = 1.0;
         let lw = 1.0;
 
         let mut lines = Vec::<gm::Line>::new();
+        let mut inferred_lines = Vec::<gm::Line>::new();
 
         for cross in crosses {
-            let x = cross.x;
-            for span in cross.spans() {
-                let mut p1 = ycbcr_frame.opengl_coords_for_index([x, span[0]]);
-                let mut p2 = ycbcr_frame.opengl_coords_for_index([x, span[1]]);
-
-                // Draw from the top of the first pixel to the bottom of the second:
-                if p2[1] < p1[1] {
-                        p1[1] += pix_h / 2.0;
-                        p2[1] -= pix_h / 2.0;
-                    } else {
-                        p1[1] -= pix_h / 2.0;
-                        p2[1] += pix_h / 2.0;
-                }
+            // Draw from where the scan line enters each span to where it leaves:
+            for (i, span) in cross.spans().enumerate() {
+                let p1 = ycbcr_frame.opengl_coords_for_point(span[0]);
+                let p2 = ycbcr_frame.opengl_coords_for_point(span[1]);
 
-                lines.push(gm::Line::new(p1, p2));
+                if cross.is_observed(i) {
+                    lines.push(gm::Line::new(p1, p2));
+                } else {
+                    inferred_lines.push(gm::Line::new(p1, p2));
+                }
             }
         }
 
         self.draw_lines(target, &lines, lw, colour);
+
+        // Draw inferred lines faded:
+        let inferred_colour = [colour[0], colour[1], colour[2], colour[3] * 0.4];
+        self.draw_lines(target, &inferred_lines, lw, inferred_colour);
     }
 
     pub fn draw_ransac_state<I: Image>(
@@ -359,7 +381,8 @@ impl<'a> DrawingFrame<'a> {
             self.set_view_matrices();
 
         if let Some(ref line) = state.model {
-            let line = gm::Line::new(line.a.centre(), line.b.centre());
+            let num_lines = line.num_lines();
+            let line = line.line();
 
             // Draw inliers:
             let inliers_col = [1.0, 0.0, 0.0, 1.0];
@@ -391,6 +414,7 @@ impl<'a> DrawingFrame<'a> {
             self.draw_staff_from_parts(&mut target, p1, p2,
                 avg_space_width,
                 avg_line_width,
+                num_lines,
                 staff_col
             );
 
@@ -1,6 +1,6 @@
 use ffmpeg_camera::image_uyvy;
 use ffmpeg_camera::image_ycbcr;
-use ffmpeg_camera::image::Image;
+use ffmpeg_camera::image::{Image, FromRawParts};
 use ffmpeg_camera::ToTexture;
 use glium;
 use glium::Surface;
@@ -0,0 +1,139 @@
+extern crate libc;
+
+// An ffmpeg input device used to capture frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptureBackend {
+    // macOS cameras and screens.
+    AvFoundation,
+    // Linux cameras (Video4Linux2).
+    V4l2,
+    // ffmpeg's virtual inputs, e.g. "testsrc" (which require no hardware).
+    Lavfi,
+}
+
+impl CaptureBackend {
+    pub fn format_name(&self) -> &'static str {
+        match *self {
+            CaptureBackend::AvFoundation => "avfoundation",
+            CaptureBackend::V4l2 => "video4linux2",
+            CaptureBackend::Lavfi => "lavfi",
+        }
+    }
+
+    pub fn from_name(name : &str) -> Option<CaptureBackend> {
+        match name {
+            "avfoundation" => Some(CaptureBackend::AvFoundation),
+            "v4l2" | "video4linux2" => Some(CaptureBackend::V4l2),
+            "lavfi" => Some(CaptureBackend::Lavfi),
+            _ => None,
+        }
+    }
+
+    // The framerate to request when a device's supported framerates are unknown.
+    // Note: avfoundation only opens devices at a framerate that they report exactly, which is
+    // 30.000030 for most UVC webcams (see FfmpegCamera::list_devices for others).
+    pub fn default_framerate(&self) -> &'static str {
+        match *self {
+            CaptureBackend::AvFoundation => "30.000030",
+            CaptureBackend::V4l2 => "30",
+            CaptureBackend::Lavfi => "30",
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn default_for_platform() -> CaptureBackend {
+        CaptureBackend::AvFoundation
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn default_for_platform() -> CaptureBackend {
+        CaptureBackend::V4l2
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    pub fn default_for_platform() -> CaptureBackend {
+        CaptureBackend::Lavfi
+    }
+}
+
+// Describes a capture device and the mode to open it in.
+#[derive(Debug, Clone)]
+pub struct CameraSpec {
+    pub backend : CaptureBackend,
+
+    // Device name (avfoundation), device path (v4l2, e.g. "/dev/video0"), or filter graph
+    // (lavfi, e.g. "testsrc").
+    pub device : String,
+
+    // Frames per second, e.g. "30.000030".
+    pub framerate : String,
+
+    // Frame width and height in pixels.
+    pub video_size : (usize, usize),
+
+    // Requested capture pixel format (e.g. "uyvy422"). If None, the device default is used.
+    pub pixel_format : Option<String>,
+}
+
+impl CameraSpec {
+    pub fn new(backend : CaptureBackend, device : &str, framerate : &str, video_size : (usize, usize)) -> CameraSpec {
+        // avfoundation cameras are slow to convert from their other formats:
+        let pixel_format = match backend {
+            CaptureBackend::AvFoundation => Some(String::from("uyvy422")),
+            _ => None,
+        };
+
+        CameraSpec {
+            backend: backend,
+            device: String::from(device),
+            framerate: String::from(framerate),
+            video_size: video_size,
+            pixel_format: pixel_format,
+        }
+    }
+
+    // A virtual test pattern input that can be used on machines without a camera.
+    pub fn test_source(video_size : (usize, usize)) -> CameraSpec {
+        let backend = CaptureBackend::Lavfi;
+        Self::new(backend, "testsrc", backend.default_framerate(), video_size)
+    }
+
+    // The filename passed to avformat_open_input.
+    pub fn input_filename(&self) -> String {
+        let video_size_str = format!("{}x{}", self.video_size.0, self.video_size.1);
+
+        match self.backend {
+            // Note the trailing ':' (avfoundation input filename format is "[[VIDEO]:[AUDIO]]").
+            CaptureBackend::AvFoundation => format!("{}:", self.device),
+            CaptureBackend::V4l2 => self.device.clone(),
+            CaptureBackend::Lavfi => {
+                // lavfi takes no options, so the mode is specified in the filter graph.
+                let mut graph = self.device.clone();
+                if !graph.contains('=') {
+                    graph = format!("{}=size={}:rate={}", graph, video_size_str, self.framerate);
+                }
+                if let Some(ref pixel_format) = self.pixel_format {
+                    graph = format!("{},format=pix_fmts={}", graph, pixel_format);
+                }
+                graph
+            },
+        }
+    }
+
+    // The options passed to avformat_open_input.
+    pub fn open_options(&self) -> Vec<(String, String, libc::c_int)> {
+        let mut options = Vec::new();
+
+        if self.backend == CaptureBackend::Lavfi {
+            return options;
+        }
+
+        if let Some(ref pixel_format) = self.pixel_format {
+            options.push((String::from("pixel_format"), pixel_format.clone(), 0));
+        }
+        options.push((String::from("framerate"), self.framerate.clone(), 0));
+        options.push((String::from("video_size"), format!("{}x{}", self.video_size.0, self.video_size.1), 0));
+
+        options
+    }
+}
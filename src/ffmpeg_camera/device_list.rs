@@ -0,0 +1,362 @@
+extern crate ffmpeg_sys;
+extern crate libc;
+use std::ptr;
+use std::ffi::CString;
+
+use ffmpeg_camera::ffmpeg_camera::FfmpegCamera;
+use ffmpeg_camera::camera_spec::{CameraSpec, CaptureBackend};
+use ffmpeg_camera::ffmpeg_utils;
+use ffmpeg_camera::ffmpeg_utils::FfmpegError;
+
+// A frame size supported by a capture device, with the framerates available at that size.
+#[derive(Debug, Clone)]
+pub struct CaptureMode {
+    pub video_size : (usize, usize),
+
+    // Note: v4l2 devices do not report framerates through ffmpeg, so this may be empty.
+    pub framerates : Vec<f64>,
+}
+
+// Modes are preferred if they support a framerate within this distance of 30fps (see
+// DeviceInfo::preferred_mode).
+const PREFERRED_FRAMERATE : f64 = 30.0;
+const MAX_PREFERRED_FRAMERATE_ERROR : f64 = 5.0;
+
+impl CaptureMode {
+    // Returns the supported framerate closest to the given one, or None if the device did not
+    // report any framerates.
+    pub fn closest_framerate(&self, framerate : f64) -> Option<f64> {
+        self.framerates.iter().cloned().fold(None, |closest : Option<f64>, r| {
+            match closest {
+                Some(c) if (c - framerate).abs() <= (r - framerate).abs() => Some(c),
+                _ => Some(r),
+            }
+        })
+    }
+
+    // Whether the mode supports a framerate near 30fps (or reports no framerates).
+    fn has_preferred_framerate(&self) -> bool {
+        self.closest_framerate(PREFERRED_FRAMERATE)
+            .map_or(true, |r| (r - PREFERRED_FRAMERATE).abs() <= MAX_PREFERRED_FRAMERATE_ERROR)
+    }
+
+    fn area(&self) -> usize {
+        self.video_size.0 * self.video_size.1
+    }
+}
+
+// Returns the mode with the largest frame size (the earliest, if several are the same size).
+fn largest_mode<'a, I : Iterator<Item = &'a CaptureMode>>(modes : I) -> Option<&'a CaptureMode> {
+    modes.fold(None, |largest : Option<&'a CaptureMode>, m| {
+        match largest {
+            Some(l) if l.area() >= m.area() => Some(l),
+            _ => Some(m),
+        }
+    })
+}
+
+// A capture device, and the modes that it supports.
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    pub backend : CaptureBackend,
+    pub name : String,
+    pub modes : Vec<CaptureMode>,
+    pub pixel_formats : Vec<String>,
+}
+
+impl DeviceInfo {
+    pub fn resolutions(&self) -> Vec<(usize, usize)> {
+        self.modes.iter().map(|m| m.video_size).collect()
+    }
+
+    // Returns a specification for opening the device in the given mode at its highest framerate.
+    pub fn camera_spec(&self, mode : &CaptureMode) -> CameraSpec {
+        let framerate = mode.framerates.iter().cloned().fold(0.0, f64::max);
+        let framerate_str = if framerate > 0.0 { format!("{:.6}", framerate) } else { String::from(self.backend.default_framerate()) };
+
+        CameraSpec::new(self.backend, &self.name, &framerate_str, mode.video_size)
+    }
+
+    // Returns a specification for opening the device in the given mode at the supported framerate
+    // closest to the given one.
+    // Note: Some backends (e.g. avfoundation) only accept the exact framerates that they report.
+    pub fn camera_spec_at(&self, mode : &CaptureMode, framerate : f64) -> CameraSpec {
+        let framerate = mode.closest_framerate(framerate).unwrap_or(framerate);
+
+        CameraSpec::new(self.backend, &self.name, &format!("{:.6}", framerate), mode.video_size)
+    }
+
+    // Returns the mode with the given frame size, if it is supported.
+    pub fn find_mode(&self, video_size : (usize, usize)) -> Option<&CaptureMode> {
+        self.modes.iter().find(|m| m.video_size == video_size)
+    }
+
+    // Chooses a mode independently of the order in which the device listed them: the largest frame
+    // size that supports a framerate near 30fps, or the largest frame size if none do.
+    // Note: Earlier modes win ties, so the choice is repeatable.
+    pub fn preferred_mode(&self) -> Option<&CaptureMode> {
+        largest_mode(self.modes.iter().filter(|m| m.has_preferred_framerate()))
+            .or_else(|| largest_mode(self.modes.iter()))
+    }
+
+    // Returns a specification for opening the device in its preferred mode (see preferred_mode),
+    // at the framerate closest to 30fps.
+    pub fn preferred_camera_spec(&self) -> Option<CameraSpec> {
+        self.preferred_mode().map(|mode| self.camera_spec_at(mode, PREFERRED_FRAMERATE))
+    }
+}
+
+// Attempts to open a device, returning everything that ffmpeg logged while doing so.
+// Devices are opened with deliberately invalid options to make them list their capabilities.
+unsafe fn probe_device(backend : CaptureBackend, filename_str : &str, options : Vec<(&str, &str, libc::c_int)>) -> String {
+    let (_, log) = ffmpeg_utils::capture_av_log(|| {
+        let format_name = CString::new(backend.format_name()).unwrap();
+        let input_format = ffmpeg_sys::av_find_input_format(format_name.as_ptr());
+        if input_format.is_null() {
+            return;
+        }
+
+        let filename = CString::new(filename_str).unwrap();
+        let mut open_options = ffmpeg_utils::create_av_dict(options);
+        let mut format_context : *mut ffmpeg_sys::AVFormatContext = ptr::null_mut();
+        let open_error = ffmpeg_sys::avformat_open_input(
+            &mut format_context,
+            filename.as_ptr(),
+            input_format,
+            &mut open_options
+        );
+        ffmpeg_sys::av_dict_free(&mut open_options);
+
+        if open_error >= 0 {
+            ffmpeg_sys::avformat_close_input(&mut format_context);
+        }
+    });
+
+    log
+}
+
+// Parses a size of the form "640x480".
+fn parse_video_size(size_str : &str) -> Option<(usize, usize)> {
+    let mut dims = size_str.trim().split('x');
+    match (dims.next(), dims.next(), dims.next()) {
+        (Some(w), Some(h), None) => {
+            match (w.parse::<usize>(), h.parse::<usize>()) {
+                (Ok(w), Ok(h)) => Some((w, h)),
+                _ => None,
+            }
+        },
+        _ => None,
+    }
+}
+
+// Adds a mode, merging its framerates with those of any existing mode of the same size.
+fn add_mode(modes : &mut Vec<CaptureMode>, video_size : (usize, usize), framerates : Vec<f64>) {
+    if let Some(mode) = modes.iter_mut().find(|m| m.video_size == video_size) {
+        for rate in framerates {
+            if !mode.framerates.contains(&rate) {
+                mode.framerates.push(rate);
+            }
+        }
+        return;
+    }
+
+    modes.push(CaptureMode {
+        video_size: video_size,
+        framerates: framerates,
+    });
+}
+
+// Parses avfoundation's device list, e.g.:
+//     AVFoundation video devices:
+//     [0] FaceTime HD Camera
+//     [1] Capture screen 0
+//     AVFoundation audio devices:
+//     [0] Built-in Microphone
+fn parse_avfoundation_devices(log : &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut in_video_section = false;
+
+    for line in log.lines() {
+        if line.contains("AVFoundation video devices") {
+            in_video_section = true;
+        } else if line.contains("AVFoundation audio devices") {
+            in_video_section = false;
+        } else if in_video_section {
+            if let (Some(open), Some(close)) = (line.find('['), line.find("] ")) {
+                if open < close {
+                    names.push(String::from(line[close + 2..].trim()));
+                }
+            }
+        }
+    }
+
+    names
+}
+
+// Parses avfoundation's supported modes, e.g.:
+//     Supported modes:
+//       640x480@[30.000030 30.000030]fps
+//       1280x720@[1.000000 30.000030]fps
+fn parse_avfoundation_modes(log : &str) -> Vec<CaptureMode> {
+    let mut modes = Vec::new();
+
+    for line in log.lines() {
+        let line = line.trim();
+        if !line.ends_with("]fps") {
+            continue;
+        }
+
+        if let (Some(at), Some(open)) = (line.find("@["), line.find('[')) {
+            let video_size = parse_video_size(&line[..at]);
+            let framerates : Vec<f64> = line[open + 1..line.len() - 4]
+                .split_whitespace()
+                .filter_map(|rate| rate.parse::<f64>().ok())
+                .collect();
+
+            if let Some(video_size) = video_size {
+                add_mode(&mut modes, video_size, framerates);
+            }
+        }
+    }
+
+    modes
+}
+
+// Parses avfoundation's supported pixel formats, e.g.:
+//     Supported pixel formats:
+//       uyvy422
+//       yuyv422
+fn parse_avfoundation_pixel_formats(log : &str) -> Vec<String> {
+    let mut formats = Vec::new();
+    let mut in_format_list = false;
+
+    for line in log.lines() {
+        if line.contains("Supported pixel formats") {
+            in_format_list = true;
+        } else if in_format_list {
+            let is_entry = line.starts_with(' ') && line.split_whitespace().count() == 1;
+            if is_entry {
+                formats.push(String::from(line.trim()));
+            } else {
+                in_format_list = false;
+            }
+        }
+    }
+
+    formats
+}
+
+// Parses the format list printed by v4l2's list_formats option, e.g.:
+//     Raw       :     yuyv422 :           YUYV 4:2:2 : 640x480 320x240
+//     Compressed:       mjpeg :          Motion-JPEG : 640x480 1280x720
+fn parse_v4l2_formats(log : &str) -> (Vec<CaptureMode>, Vec<String>) {
+    let mut modes = Vec::new();
+    let mut pixel_formats = Vec::new();
+
+    for line in log.lines() {
+        let fields : Vec<&str> = line.split(" : ").collect();
+        let is_format_line = fields.len() >= 4 &&
+            (fields[0].trim().starts_with("Raw") || fields[0].trim().starts_with("Compressed"));
+        if !is_format_line {
+            continue;
+        }
+
+        let pixel_format = String::from(fields[1].trim());
+        if !pixel_formats.contains(&pixel_format) {
+            pixel_formats.push(pixel_format);
+        }
+
+        for size_str in fields[fields.len() - 1].split_whitespace() {
+            if let Some(video_size) = parse_video_size(size_str) {
+                add_mode(&mut modes, video_size, Vec::new());
+            }
+        }
+    }
+
+    (modes, pixel_formats)
+}
+
+unsafe fn list_avfoundation_devices() -> Vec<DeviceInfo> {
+    let backend = CaptureBackend::AvFoundation;
+    let list_log = probe_device(backend, "", vec!(("list_devices", "true", 0)));
+
+    parse_avfoundation_devices(&list_log).into_iter().map(|name| {
+        let device_filename = format!("{}:", name);
+
+        // An unsupported framerate makes avfoundation list the supported modes:
+        let modes_log = probe_device(backend, &device_filename, vec!(("framerate", "0.1", 0)));
+
+        // An unsupported pixel format makes avfoundation list the supported formats:
+        let formats_log = probe_device(backend, &device_filename, vec!(("pixel_format", "pal8", 0)));
+
+        DeviceInfo {
+            backend: backend,
+            name: name,
+            modes: parse_avfoundation_modes(&modes_log),
+            pixel_formats: parse_avfoundation_pixel_formats(&formats_log),
+        }
+    }).collect()
+}
+
+unsafe fn list_v4l2_devices() -> Result<Vec<DeviceInfo>, FfmpegError> {
+    let backend = CaptureBackend::V4l2;
+
+    let format_name = CString::new(backend.format_name()).unwrap();
+    let input_format = ffmpeg_sys::av_find_input_format(format_name.as_ptr());
+    if input_format.is_null() {
+        return Err(FfmpegError::from_message("list_v4l2_devices: video4linux2 input format not found."));
+    }
+
+    let mut device_list : *mut ffmpeg_sys::AVDeviceInfoList = ptr::null_mut();
+    let list_error = ffmpeg_sys::avdevice_list_input_sources(
+        input_format,
+        ptr::null(),
+        ptr::null_mut(),
+        &mut device_list
+    );
+    if list_error < 0 {
+        ffmpeg_utils::log_av_error("avdevice_list_input_sources", list_error);
+        return Err(FfmpegError::from_av_error(list_error));
+    }
+
+    let mut device_names = Vec::new();
+    for i in 0..(*device_list).nb_devices {
+        let device = *(*device_list).devices.offset(i as isize);
+        device_names.push(ffmpeg_utils::cstring_to_str_safe((*device).device_name));
+    }
+    ffmpeg_sys::avdevice_free_list_devices(&mut device_list);
+
+    Ok(device_names.into_iter().map(|name| {
+        let formats_log = probe_device(backend, &name, vec!(("list_formats", "all", 0)));
+        let (modes, pixel_formats) = parse_v4l2_formats(&formats_log);
+
+        DeviceInfo {
+            backend: backend,
+            name: name,
+            modes: modes,
+            pixel_formats: pixel_formats,
+        }
+    }).collect())
+}
+
+impl FfmpegCamera {
+    // Lists the capture devices available through the platform's default backend.
+    pub fn list_devices() -> Result<Vec<DeviceInfo>, FfmpegError> {
+        Self::list_backend_devices(CaptureBackend::default_for_platform())
+    }
+
+    pub fn list_backend_devices(backend : CaptureBackend) -> Result<Vec<DeviceInfo>, FfmpegError> {
+        unsafe {
+            // Register available devices with ffmpeg.
+            ffmpeg_sys::avdevice_register_all();
+            ffmpeg_sys::av_register_all();
+
+            match backend {
+                CaptureBackend::AvFoundation => Ok(list_avfoundation_devices()),
+                CaptureBackend::V4l2 => list_v4l2_devices(),
+                // Virtual inputs are not devices.
+                CaptureBackend::Lavfi => Ok(Vec::new()),
+            }
+        }
+    }
+}
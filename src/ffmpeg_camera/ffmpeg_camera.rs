@@ -12,12 +12,24 @@ use ffmpeg_camera::image;
 
 use ffmpeg_camera::ffmpeg_utils;
 use ffmpeg_camera::ffmpeg_utils::FfmpegError;
+use ffmpeg_camera::camera_spec::{CameraSpec, CaptureBackend};
 
 pub struct FfmpegCamera {
     stream_index : usize,
     format_context : *mut ffmpeg_sys::AVFormatContext,
     decoder_context : *mut ffmpeg_sys::AVCodecContext,
     frame_raw : *mut ffmpeg_sys::AVFrame,
+
+    // Playback state (only meaningful for file inputs):
+    // The demuxer has no more packets, but the decoder may still hold delayed frames.
+    end_of_file : bool,
+    // The decoder has been flushed, and no more frames are available.
+    end_of_stream : bool,
+    // frame_raw holds a decoded frame that has not yet been returned (e.g. after seeking).
+    has_pending_frame : bool,
+    // Index and presentation timestamp (in stream time_base units) of the frame in frame_raw.
+    frame_index : Option<usize>,
+    frame_pts : Option<i64>,
 }
 
 impl Drop for FfmpegCamera {
@@ -36,52 +48,40 @@ impl Drop for FfmpegCamera {
 }
 
 impl FfmpegCamera {
-    unsafe fn get_default_format_context(device_filename_str : &str, framerate_str : &str, frame_dims : (usize, usize)) -> Result<*mut ffmpeg_sys::AVFormatContext, FfmpegError> {
-
-        // Get the avfoundation format:
-        let format_name = CString::new("avfoundation").unwrap();
-        let input_format = ffmpeg_sys::av_find_input_format(format_name.as_ptr());
-        println!("input_format: {:?}", input_format);
-        println!("input_format: {:?}", ffmpeg_utils::cstring_to_str_safe((*input_format).long_name));
+    unsafe fn open_format_context(
+        filename_str : &str,
+        input_format_name : Option<&str>,
+        options : Vec<(&str, &str, libc::c_int)>)
+        -> Result<*mut ffmpeg_sys::AVFormatContext, FfmpegError> {
+
+        // Find the requested input format. If no format is given, ffmpeg will probe the input to
+        // determine its format (e.g. for image and video files).
+        let mut input_format = ptr::null_mut();
+        if let Some(format_name_str) = input_format_name {
+            let format_name = CString::new(format_name_str).unwrap();
+            input_format = ffmpeg_sys::av_find_input_format(format_name.as_ptr());
+            if input_format.is_null() {
+                let message = format!("av_find_input_format: input format '{}' not found.", format_name_str);
+                println!("{}", message);
+                return Err(FfmpegError::from_message(&message));
+            }
+            println!("input_format: {:?}", ffmpeg_utils::cstring_to_str_safe((*input_format).long_name));
+        }
 
-        // Note the trailing ':' (avfoundation input filename format is "[[VIDEO]:[AUDIO]]").
-        // Note: avdevice_list_input_sources is not implemented for avfoundation.
-        // Instead, run the following command to find an appropriate device name.
-        // $ ffmpeg -f avfoundation -list_devices true -i "default" -v 1000
-        // let device_filename_str = format!("{}:", video_filename);
-
-        let video_size_str = format!("{}x{}", frame_dims.0, frame_dims.1);
-        let device_filename = CString::new(device_filename_str).unwrap();
-        let mut open_options = ffmpeg_utils::create_av_dict(vec!(
-            // ("list_devices", "1", 0),
-            // ("video_device_index", "0", 0),
-            // ("audio_device_index", "0", 0),
-            ("pixel_format", "uyvy422", 0),
-            // ("framerate", "14.999993", 0),
-            // ("video_size", "1280x720", 0),
-            // ("video_size", "1920x1080", 0),
-            // ("framerate", "30.000030", 0),
-            ("framerate", framerate_str, 0),
-            ("video_size", &video_size_str, 0),
-            // ("capture_cursor", "1", 0),
-            // ("capture_mouse_clicks", "1", 0),
-        ));
-        println!("device_filename: {:?}", device_filename);
+        let filename = CString::new(filename_str).unwrap();
+        let mut open_options = ffmpeg_utils::create_av_dict(options);
+        println!("filename: {:?}", filename);
         println!("open_options: {}", ffmpeg_utils::av_dict_string(open_options));
 
         // let mut format_context = ffmpeg_sys::avformat_alloc_context();
         let mut format_context : *mut ffmpeg_sys::AVFormatContext = ptr::null_mut();
 
-        println!("format_context: {:?}", format_context);
-
         let open_error = ffmpeg_sys::avformat_open_input(
             &mut format_context,
-            device_filename.as_ptr(),
+            filename.as_ptr(),
             input_format,
-            // &mut open_options
             &mut open_options
         );
-        println!("format_context: {:?}", format_context);
 
         let num_rejected_options = ffmpeg_sys::av_dict_count(&*open_options);
         if num_rejected_options > 0 {
@@ -101,12 +101,26 @@ impl FfmpegCamera {
 
         if stream_info_error < 0 {
             ffmpeg_utils::log_av_error("stream_info_error", stream_info_error);
+            ffmpeg_sys::avformat_close_input(&mut format_context);
             return Err(FfmpegError::from_av_error(stream_info_error));
         }
 
         Ok(format_context)
     }
 
+    unsafe fn get_device_format_context(spec : &CameraSpec) -> Result<*mut ffmpeg_sys::AVFormatContext, FfmpegError> {
+        // Note: avdevice_list_input_sources is not implemented for avfoundation.
+        // Instead, run the following command to find an appropriate device name.
+        // $ ffmpeg -f avfoundation -list_devices true -i "default" -v 1000
+
+        let options = spec.open_options();
+        let open_options = options.iter()
+            .map(|&(ref key, ref val, flags)| (key.as_str(), val.as_str(), flags))
+            .collect();
+
+        Self::open_format_context(&spec.input_filename(), Some(spec.backend.format_name()), open_options)
+    }
+
     unsafe fn get_codec_context(format_context : *mut ffmpeg_sys::AVFormatContext, stream_index : isize) -> Result<*mut ffmpeg_sys::AVCodecContext, FfmpegError> {
         let stream = (*(*format_context).streams).offset(stream_index as isize);
         let decoder_context = (*stream).codec;
@@ -186,28 +200,82 @@ impl FfmpegCamera {
         Ok(())
     }
 
+    unsafe fn stream(&self) -> *mut ffmpeg_sys::AVStream {
+        *(*self.format_context).streams.offset(self.stream_index as isize)
+    }
+
+    // Stores the timestamp and index of the frame that was just decoded into frame_raw.
+    unsafe fn update_frame_position(&mut self) {
+        let pts = ffmpeg_sys::av_frame_get_best_effort_timestamp(self.frame_raw);
+        self.frame_pts = if pts == ffmpeg_sys::AV_NOPTS_VALUE { None } else { Some(pts) };
+
+        let next_index = self.frame_index.map_or(0, |i| i + 1);
+        self.frame_index = match (self.frame_timestamp(), self.frame_rate()) {
+            (Some(time), Some(fps)) => Some((time * fps).round().max(0.0) as usize),
+            _ => Some(next_index),
+        };
+    }
+
     unsafe fn read_next_frame(&mut self) -> Result<(), FfmpegError> {
-        let mut packet_vec: Vec<u8> = Vec::with_capacity(mem::size_of::<ffmpeg_sys::AVPacket>());
-        let mut packet : *mut ffmpeg_sys::AVPacket = packet_vec.as_mut_ptr() as *mut ffmpeg_sys::AVPacket;
+        // A frame may have already been decoded while seeking:
+        if self.has_pending_frame {
+            self.has_pending_frame = false;
+            return Ok(());
+        }
 
-        // initialize packet, set data to NULL, let the demuxer fill it
-        ffmpeg_sys::av_init_packet(packet);
-        (*packet).data = ptr::null_mut();
-        (*packet).size = 0;
+        if self.end_of_stream {
+            return Err(FfmpegError::from_av_error(ffmpeg_utils::AVERROR_EOF));
+        }
+
+        let mut packet_vec: Vec<u8> = Vec::with_capacity(mem::size_of::<ffmpeg_sys::AVPacket>());
+        let packet : *mut ffmpeg_sys::AVPacket = packet_vec.as_mut_ptr() as *mut ffmpeg_sys::AVPacket;
 
         let mut result = Ok(());
 
         // Read frames until a valid frame is read:
         let mut got_frame = 0;
         while got_frame == 0 {
+            // initialize packet, set data to NULL, let the demuxer fill it
+            ffmpeg_sys::av_init_packet(packet);
+            (*packet).data = ptr::null_mut();
+            (*packet).size = 0;
+
+            if self.end_of_file {
+                // Drain the frames buffered by the decoder by sending it empty packets:
+                (*packet).stream_index = self.stream_index as i32;
+                let decode_result = self.decode_packet(packet, &mut got_frame);
+                if decode_result.is_err() {
+                    result = decode_result;
+                    break;
+                }
+                if got_frame == 0 {
+                    self.end_of_stream = true;
+                    result = Err(FfmpegError::from_av_error(ffmpeg_utils::AVERROR_EOF));
+                    break;
+                }
+                continue;
+            }
+
             let read_error = ffmpeg_sys::av_read_frame(self.format_context, packet);
             if read_error >= 0 {
+                // Skip packets from other streams (e.g. audio in video files):
+                if (*packet).stream_index != self.stream_index as i32 {
+                    ffmpeg_sys::av_free_packet(packet);
+                    continue;
+                }
+
                 let decode_result = self.decode_packet(packet, &mut got_frame);
+
+                // Free the packet's data buffers:
+                ffmpeg_sys::av_free_packet(packet);
+
                 if decode_result.is_err() {
                     // ffmpeg_utils::log_av_error("read_next_frame, decode_packet", decoded_size);
                     result = decode_result; //Err(FfmpegError::from_av_error(decoded_size))
                     break;
                 }
+            } else if read_error == ffmpeg_utils::AVERROR_EOF {
+                self.end_of_file = true;
             } else {
                 ffmpeg_utils::log_av_error("read_next_frame, av_read_frame", read_error);
                 result = Err(FfmpegError::from_av_error(read_error));
@@ -215,13 +283,16 @@ impl FfmpegCamera {
             }
         }
 
-        // Free the packet's data buffers:
-        ffmpeg_sys::av_free_packet(packet);
+        if result.is_ok() {
+            self.update_frame_position();
+        }
+
         result
     }
 
-    pub fn get_image<I : image::Image>(&mut self, target_format : ffmpeg_sys::AVPixelFormat)
-        -> Result<I, FfmpegError> {
+    // Reads the next frame, returning its dimensions and its data converted to target_format.
+    fn get_frame_data(&mut self, target_format : ffmpeg_sys::AVPixelFormat)
+        -> Result<(usize, usize, Vec<u8>), FfmpegError> {
         let data : Vec<u8>;
         let width;
         let height;
@@ -233,7 +304,7 @@ impl FfmpegCamera {
             let mut src_frame = self.frame_raw;
             let mut converted_frame = ptr::null_mut::<ffmpeg_sys::AVFrame>();
 
-            let frame_format = ffmpeg_utils::av_pix_fmt_from_i32((*self.frame_raw).format);
+            let frame_format = try!(ffmpeg_utils::av_pix_fmt_from_i32((*self.frame_raw).format));
             if frame_format != target_format {
                 // Create a frame for format conversion:
                 converted_frame = try!(ffmpeg_utils::convert_frame(self.frame_raw, target_format));
@@ -250,6 +321,13 @@ impl FfmpegCamera {
             ffmpeg_sys::av_frame_free(&mut converted_frame);
         }
 
+        Ok((width, height, data))
+    }
+
+    pub fn get_image<I : image::FromRawParts>(&mut self, target_format : ffmpeg_sys::AVPixelFormat)
+        -> Result<I, FfmpegError> {
+        let (width, height, data) = try!(self.get_frame_data(target_format));
+
         Ok(I::from_raw_parts(width, height, data))
     }
 
@@ -258,12 +336,16 @@ impl FfmpegCamera {
     }
 
     pub fn get_image_nv12(&mut self) -> Result<image_nv12::Image, FfmpegError> {
-        self.get_image::<image_nv12::Image>(ffmpeg_sys::AV_PIX_FMT_YUV420P)
+        self.get_image::<image_nv12::Image>(image_nv12::PIXEL_FORMAT)
     }
 
-    // pub fn get_image_ycbcr(&mut self) -> Result<image_ycbcr::Image, FfmpegError> {
-    //     self.get_image::<image_ycbcr::Image>(ffmpeg_sys::AV_PIX_FMT_YUV444P)
-    // }
+    pub fn get_image_ycbcr(&mut self) -> Result<image_ycbcr::Image, FfmpegError> {
+        // Note: ffmpeg has no packed 8-bit YCbCrA format, so we read planar YUV444 data and
+        // interleave it ourselves.
+        let (width, height, data) = try!(self.get_frame_data(ffmpeg_sys::AV_PIX_FMT_YUV444P));
+
+        Ok(image_ycbcr::Image::from_planar_yuv444(width, height, &data))
+    }
 
     // pub fn get_image_uyvy(&mut self) -> Result<image_uyvy::Image, FfmpegError> {
     //     let mut data : Vec<u8>;
@@ -320,97 +402,253 @@ impl FfmpegCamera {
     //     })
     // }
 
-    pub fn get_camera(video_filename : &str, framerate_str : &str, video_size : (usize, usize)) -> Result<FfmpegCamera, FfmpegError> {
+    unsafe fn from_format_context(format_context : *mut ffmpeg_sys::AVFormatContext, filename_str : &str) -> Result<FfmpegCamera, FfmpegError> {
 
         let mut camera = FfmpegCamera {
             stream_index: 0,
-            format_context: ptr::null_mut(),
+            format_context: format_context,
             decoder_context: ptr::null_mut(),
             frame_raw: ptr::null_mut(),
+            end_of_file: false,
+            end_of_stream: false,
+            has_pending_frame: false,
+            frame_index: None,
+            frame_pts: None,
         };
 
+        // Find the best stream:
+        let kind = ffmpeg_sys::AVMEDIA_TYPE_VIDEO;
+        let wanted_stream_nb = -1;
+        let related_stream = -1;
+        let ecoder_ret : *mut *mut ffmpeg_sys::AVCodec = ptr::null_mut::<*mut ffmpeg_sys::AVCodec>();
+        let flags = 0;
+        let stream_index = ffmpeg_sys::av_find_best_stream(
+            format_context,
+            kind,
+            wanted_stream_nb,
+            related_stream,
+            ecoder_ret,
+            flags
+        );
+        if stream_index < 0 {
+            let type_str = ffmpeg_utils::cstring_to_str_safe(ffmpeg_sys::av_get_media_type_string(kind));
+            println!("av_find_best_stream: Could not find {} stream in input file.", type_str);
+            return Err(FfmpegError::from_av_error(stream_index));
+        }
+        println!("av_find_best_stream: stream_index: {:?}", stream_index);
+
+        let decoder_context = try!(Self::get_codec_context(format_context, stream_index as isize));
+
+        println!("av_dump_format");
+        let index = 0;
+        let is_output = 0;
+        let filename = CString::new(filename_str).unwrap();
+        ffmpeg_sys::av_dump_format(format_context, index, filename.as_ptr(), is_output);
+
+        let frame_raw = ffmpeg_sys::av_frame_alloc();
+        if frame_raw.is_null() {
+            println!("ERROR: av_frame_alloc failure.");
+            return Err(FfmpegError::from_message("ERROR: av_frame_alloc failure"));
+        }
+
+        camera.decoder_context = decoder_context;
+        camera.stream_index = stream_index as usize;
+        camera.frame_raw = frame_raw;
+
+        Ok(camera)
+    }
+
+    pub fn get_camera(spec : &CameraSpec) -> Result<FfmpegCamera, FfmpegError> {
         unsafe {
             // Register available file formats and codecs with ffmpeg.
             ffmpeg_sys::avdevice_register_all();
             ffmpeg_sys::av_register_all();
 
-            let device_filename_str = format!("{}:", video_filename);
-            let format_context = try!(Self::get_default_format_context(&device_filename_str, framerate_str, video_size));
-
-            // Find the best stream:
-            let kind = ffmpeg_sys::AVMEDIA_TYPE_VIDEO;
-            let wanted_stream_nb = -1;
-            let related_stream = -1;
-            let ecoder_ret : *mut *mut ffmpeg_sys::AVCodec = ptr::null_mut::<*mut ffmpeg_sys::AVCodec>();
-            let flags = 0;
-            let stream_index = ffmpeg_sys::av_find_best_stream(
-                format_context,
-                kind,
-                wanted_stream_nb,
-                related_stream,
-                ecoder_ret,
-                flags
-            );
-            if stream_index < 0 {
-                let type_str = ffmpeg_utils::cstring_to_str_safe(ffmpeg_sys::av_get_media_type_string(kind));
-                println!("av_find_best_stream: Could not find {} stream in input file.", type_str);
-                return Err(FfmpegError::from_av_error(stream_index));
+            let format_context = try!(Self::get_device_format_context(spec));
+
+            Self::from_format_context(format_context, &spec.input_filename())
+        }
+    }
+
+    // Opens a media file (e.g. a still image), letting ffmpeg detect its format.
+    pub fn open_file(file_name : &str) -> Result<FfmpegCamera, FfmpegError> {
+        unsafe {
+            // Register available file formats and codecs with ffmpeg.
+            ffmpeg_sys::av_register_all();
+
+            let format_context = try!(Self::open_format_context(file_name, None, Vec::new()));
+
+            Self::from_format_context(format_context, file_name)
+        }
+    }
+
+    // Opens a video file (e.g. mp4, mkv, or mov) for frame-by-frame playback.
+    pub fn open_video(file_name : &str) -> Result<FfmpegCamera, FfmpegError> {
+        Self::open_file(file_name)
+    }
+
+    // Returns true once every frame in the input has been read.
+    pub fn is_end_of_stream(&self) -> bool {
+        self.end_of_stream
+    }
+
+    // Index of the most recently read frame.
+    pub fn frame_index(&self) -> Option<usize> {
+        self.frame_index
+    }
+
+    // Presentation time of the most recently read frame, in seconds from the start of the stream.
+    pub fn frame_timestamp(&self) -> Option<f64> {
+        unsafe {
+            let stream = self.stream();
+            let time_base = (*stream).time_base;
+            let start_time = if (*stream).start_time == ffmpeg_sys::AV_NOPTS_VALUE { 0 } else { (*stream).start_time };
+
+            self.frame_pts.map(|pts| {
+                (pts - start_time) as f64 * time_base.num as f64 / time_base.den as f64
+            })
+        }
+    }
+
+    // Average number of frames per second of the stream, if known.
+    pub fn frame_rate(&self) -> Option<f64> {
+        unsafe {
+            let rate = (*self.stream()).avg_frame_rate;
+            if rate.num <= 0 || rate.den <= 0 {
+                return None;
             }
-            println!("av_find_best_stream: stream_index: {:?}", stream_index);
 
-            let decoder_context = try!(Self::get_codec_context(format_context, stream_index as isize));
+            Some(rate.num as f64 / rate.den as f64)
+        }
+    }
 
-            println!("av_dump_format");
-            let index = 0;
-            let is_output = 0;
-            let device_filename = CString::new(device_filename_str).unwrap();
-            ffmpeg_sys::av_dump_format(format_context, index, device_filename.as_ptr(), is_output);
+    // Duration of the stream in seconds, if known.
+    pub fn duration(&self) -> Option<f64> {
+        unsafe {
+            let stream = self.stream();
+            if (*stream).duration == ffmpeg_sys::AV_NOPTS_VALUE || (*stream).duration <= 0 {
+                return None;
+            }
+
+            let time_base = (*stream).time_base;
+            Some((*stream).duration as f64 * time_base.num as f64 / time_base.den as f64)
+        }
+    }
 
-            let frame_raw = ffmpeg_sys::av_frame_alloc();
-            if frame_raw.is_null() {
-                println!("ERROR: av_frame_alloc failure.");
-                return Err(FfmpegError::from_message("ERROR: av_frame_alloc failure"));
+    // Number of frames in the stream, if known.
+    pub fn num_frames(&self) -> Option<usize> {
+        unsafe {
+            let nb_frames = (*self.stream()).nb_frames;
+            if nb_frames > 0 {
+                return Some(nb_frames as usize);
             }
+        }
 
-            camera.format_context = format_context;
-            camera.decoder_context = decoder_context;
-            camera.stream_index = stream_index as usize;
-            camera.frame_raw = frame_raw;
+        match (self.duration(), self.frame_rate()) {
+            (Some(duration), Some(fps)) => Some((duration * fps).round() as usize),
+            _ => None,
         }
+    }
 
-        Ok(camera)
+    // Seeks so that the next frame read is the first frame at or after the given time (in
+    // seconds from the start of the stream).
+    pub fn seek_to_time(&mut self, seconds : f64) -> Result<(), FfmpegError> {
+        unsafe {
+            let stream = self.stream();
+            let time_base = (*stream).time_base;
+            let start_time = if (*stream).start_time == ffmpeg_sys::AV_NOPTS_VALUE { 0 } else { (*stream).start_time };
+            let target_pts = start_time + (seconds.max(0.0) * time_base.den as f64 / time_base.num as f64).round() as i64;
+
+            // Seek to the keyframe before the target:
+            let seek_error = ffmpeg_sys::av_seek_frame(
+                self.format_context,
+                self.stream_index as i32,
+                target_pts,
+                ffmpeg_sys::AVSEEK_FLAG_BACKWARD
+            );
+            if seek_error < 0 {
+                ffmpeg_utils::log_av_error("seek_to_time, av_seek_frame", seek_error);
+                return Err(FfmpegError::from_av_error(seek_error));
+            }
+
+            ffmpeg_sys::avcodec_flush_buffers(self.decoder_context);
+            self.end_of_file = false;
+            self.end_of_stream = false;
+            self.has_pending_frame = false;
+            self.frame_index = None;
+            self.frame_pts = None;
+
+            // Decode forward from the keyframe until the target frame is reached:
+            loop {
+                try!(self.read_next_frame());
+
+                let reached_target = match self.frame_pts {
+                    Some(pts) => pts >= target_pts,
+                    None => true,
+                };
+                if reached_target {
+                    self.has_pending_frame = true;
+                    break;
+                }
+            }
+        }
+
+        Ok(())
     }
 
+    // Seeks so that the next frame read is the frame with the given index.
+    pub fn seek_to_frame(&mut self, index : usize) -> Result<(), FfmpegError> {
+        let fps = try!(self.frame_rate().ok_or(
+            FfmpegError::from_message("seek_to_frame: stream frame rate is unknown.")
+        ));
+
+        self.seek_to_time(index as f64 / fps)
+    }
 
     pub fn get_default() -> Result<FfmpegCamera, FfmpegError> {
         let video_size = (640, 480);
         // let video_size = (1280, 720);
         // let video_filename = "HD Pro Webcam C920";
-        let video_filename = "default";
         // let video_filename = "FaceTime HD Camera (Built-in)";
         // let video_filename = "Capture screen 0";
         // let framerate = "30.000030";
-        let framerate = "29.97";
 
-        Self::get_camera(&video_filename, framerate, video_size)
+        let backend = CaptureBackend::default_for_platform();
+        let spec = match backend {
+            CaptureBackend::AvFoundation => CameraSpec::new(backend, "default", "29.97", video_size),
+            CaptureBackend::V4l2 => CameraSpec::new(backend, "/dev/video0", "30", video_size),
+            CaptureBackend::Lavfi => CameraSpec::test_source(video_size),
+        };
+
+        Self::get_camera(&spec)
     }
 
     pub fn get_best(video_size: (usize, usize)) -> Result<FfmpegCamera, FfmpegError> {
-        let mut camera =
-            Self::get_camera("HD Pro Webcam C920", "30.000030", video_size);
-
-        if camera.is_err() {
-            println!("Failed to open HD Pro Webcam C920. Opening USB Camera...");
-            camera =
-                Self::get_camera("USB Camera", "30.000030", video_size);
+        let backend = CaptureBackend::default_for_platform();
+        let candidates = match backend {
+            CaptureBackend::AvFoundation => vec!(
+                CameraSpec::new(backend, "HD Pro Webcam C920", "30.000030", video_size),
+                CameraSpec::new(backend, "USB Camera", "30.000030", video_size),
+                CameraSpec::new(backend, "default", "29.970000", video_size),
+            ),
+            CaptureBackend::V4l2 => vec!(
+                CameraSpec::new(backend, "/dev/video0", "30", video_size),
+                CameraSpec::new(backend, "/dev/video1", "30", video_size),
+            ),
+            CaptureBackend::Lavfi => vec!(),
+        };
 
-            if camera.is_err() {
-                println!("Failed to open camera. Opening default camera...");
-                camera = Self::get_camera("default", "29.970000", video_size);
+        for spec in &candidates {
+            let camera = Self::get_camera(spec);
+            if camera.is_ok() {
+                return camera;
             }
+            println!("Failed to open {}. Trying next camera...", spec.device);
         }
 
-        camera
+        // Note: The test source is not used as a fallback, as detection would then silently run
+        // on a test pattern. It can be opened explicitly (e.g. with the "lavfi:testsrc" source).
+        Err(FfmpegError::from_message("get_best: Failed to open a camera."))
     }
 
 }
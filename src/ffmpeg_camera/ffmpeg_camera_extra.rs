@@ -1,28 +1,5 @@
 
 
-// fn get_device_list() {
-//     // Note: avdevice_list_input_sources is not implemented for avfoundation.
-//     // Instead, run the following command to find an appropriate device name.
-//     // $ ffmpeg -f avfoundation -list_devices true -i "default" -v 1000
-//
-//     // Get the avfoundation format:
-//     let format_name = CString::new("avfoundation").unwrap();
-//     let device_format = ffmpeg_sys::av_find_input_format(format_name.as_ptr());
-//     println!("device_format: {:?}", cstring_to_str_safe((*device_format).long_name));
-//
-//     let device_name = ptr::null_mut();
-//     let device_options = ptr::null_mut();
-//     let mut device_list = ptr::null_mut(); //: ffmpeg_sys::AVDeviceInfoList**
-//     let error = ffmpeg_sys::avdevice_list_input_sources(
-//         device_format,
-//         device_name,
-//         device_options,
-//         &mut device_list
-//     );
-//     println!("AVERROR ({}): {}.", error, av_error_string(error));
-// }
-
-
 // unsafe fn pgm_save(buffer : *const u8, img_w : usize, img_h : usize, stride : usize, fname: &str) {
 //     // let mut file = OpenOptions::new()
 //     //     .create(true)
@@ -3,6 +3,8 @@ extern crate libc;
 
 use std::mem;
 use std::ptr;
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
 use std::ffi::CStr;
 use std::ffi::CString;
 
@@ -13,6 +15,9 @@ use std::fmt;
 
 use utility;
 
+// Returned when the end of a stream is reached (FFERRTAG('E','O','F',' ')).
+pub const AVERROR_EOF : libc::c_int = -0x20464F45;
+
 // Define an error type for FFmpeg:
 #[derive(Debug)]
 pub struct FfmpegError {
@@ -43,6 +48,10 @@ impl FfmpegError {
             message: String::from_str(message).unwrap(),
         }
     }
+
+    pub fn is_end_of_stream(&self) -> bool {
+        self.errnum == AVERROR_EOF
+    }
 }
 
 // From: https://doc.rust-lang.org/std/ffi/struct.CStr.html
@@ -95,6 +104,113 @@ pub fn av_dict_string(dict : *const ffmpeg_sys::AVDictionary) -> String {
     result_string
 }
 
+// An av_log capture in progress.
+struct CapturedAvLog {
+    // Note: av_log callbacks are global, and are also called from other threads (e.g. decoder
+    // threads of other cameras), so only messages from the capturing thread are captured.
+    thread : thread::ThreadId,
+    log : String,
+}
+
+// Storage for av_log messages while they are being captured.
+// Note: This is only set while AV_LOG_CAPTURE_LOCK is held (see capture_av_log), and is locked
+// separately so that the callback never waits for a capture to finish.
+static CAPTURED_AV_LOG : Mutex<Option<CapturedAvLog>> = Mutex::new(None);
+static AV_LOG_CAPTURE_LOCK : Mutex<()> = Mutex::new(());
+
+fn captured_av_log() -> MutexGuard<'static, Option<CapturedAvLog>> {
+    // Note: The capture is replaced by each capture_av_log call, so a poisoned lock is still usable.
+    CAPTURED_AV_LOG.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+// Restores the default av_log callback and level when dropped, so that they are restored even if
+// the captured function panics.
+struct AvLogCaptureGuard {
+    previous_level : libc::c_int,
+    // Note: Released after drop() has run.
+    _lock : MutexGuard<'static, ()>,
+}
+
+impl AvLogCaptureGuard {
+    fn take_log(&self) -> String {
+        captured_av_log().take().map(|captured| captured.log).unwrap_or(String::new())
+    }
+}
+
+impl Drop for AvLogCaptureGuard {
+    fn drop(&mut self) {
+        unsafe {
+            ffmpeg_sys::av_log_set_callback(Some(ffmpeg_sys::av_log_default_callback));
+        }
+        *captured_av_log() = None;
+        unsafe {
+            ffmpeg_sys::av_log_set_level(self.previous_level);
+        }
+    }
+}
+
+extern "C" fn capture_av_log_callback(
+    avcl : *mut libc::c_void,
+    level : libc::c_int,
+    fmt : *const libc::c_char,
+    vl : ffmpeg_sys::va_list) {
+
+    let mut captured = captured_av_log();
+    let capturing_thread = captured.as_ref().map_or(false, |captured| captured.thread == thread::current().id());
+
+    unsafe {
+        // Messages from other threads are logged as usual:
+        if !capturing_thread {
+            drop(captured);
+            ffmpeg_sys::av_log_default_callback(avcl, level, fmt, vl);
+            return;
+        }
+
+        if level > ffmpeg_sys::AV_LOG_INFO {
+            return;
+        }
+
+        let line_size = 1024;
+        let mut line = vec![0 as libc::c_char; line_size];
+        let mut print_prefix = 0;
+        ffmpeg_sys::av_log_format_line(avcl, level, fmt, vl, line.as_mut_ptr(), line_size as libc::c_int, &mut print_prefix);
+
+        if let Some(ref mut captured) = *captured {
+            captured.log.push_str(&cstring_to_str_safe(line.as_ptr()));
+        }
+    }
+}
+
+// Calls f, returning its result along with everything it logged through av_log.
+// This is required to query devices that only report information via the log (e.g. avfoundation).
+// Note: Concurrent captures are serialised, and only messages logged by the calling thread are
+// captured.
+pub fn capture_av_log<T, F : FnOnce() -> T>(f : F) -> (T, String) {
+    // Note: A panic in a previous capture only poisons the lock after the guard has restored the
+    // callback, so the lock can still be used.
+    let lock = AV_LOG_CAPTURE_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
+    *captured_av_log() = Some(CapturedAvLog {
+        thread: thread::current().id(),
+        log: String::new(),
+    });
+    let guard = unsafe {
+        let previous_level = ffmpeg_sys::av_log_get_level();
+        ffmpeg_sys::av_log_set_level(ffmpeg_sys::AV_LOG_INFO);
+        ffmpeg_sys::av_log_set_callback(Some(capture_av_log_callback));
+
+        AvLogCaptureGuard {
+            previous_level: previous_level,
+            _lock: lock,
+        }
+    };
+
+    let result = f();
+    let log = guard.take_log();
+
+    (result, log)
+}
+
 pub unsafe fn create_av_dict(entries : Vec<(&str, &str, libc::c_int)>) -> *mut ffmpeg_sys::AVDictionary {
     let mut dict : *mut ffmpeg_sys::AVDictionary = ptr::null_mut();
 
@@ -108,20 +224,57 @@ pub unsafe fn create_av_dict(entries : Vec<(&str, &str, libc::c_int)>) -> *mut f
     dict
 }
 
-pub fn av_pix_fmt_from_i32(pix_fmt: i32) -> ffmpeg_sys::AVPixelFormat {
-    match pix_fmt {
+// Returns FFmpeg's name for a pixel format (e.g. "yuv420p").
+pub fn av_pix_fmt_name(pixel_format: ffmpeg_sys::AVPixelFormat) -> String {
+    unsafe {
+        let name = ffmpeg_sys::av_get_pix_fmt_name(pixel_format);
+        if name.is_null() {
+            return String::new();
+        }
+        cstring_to_str_safe(name)
+    }
+}
+
+// Returns the pixel format of an AVFrame's format field.
+// Note: Formats without an entry below are looked up in FFmpeg's pixel format descriptors, so that
+// uncommon decoder outputs (e.g. MONOBLACK, YA8, RGB48) can still be converted with swscale.
+pub fn av_pix_fmt_from_i32(pix_fmt: i32) -> Result<ffmpeg_sys::AVPixelFormat, FfmpegError> {
+    let pixel_format = match pix_fmt {
         0 => ffmpeg_sys::AV_PIX_FMT_YUVJ420P,
+        1 => ffmpeg_sys::AV_PIX_FMT_YUYV422,
         2 => ffmpeg_sys::AV_PIX_FMT_RGB24,
+        3 => ffmpeg_sys::AV_PIX_FMT_BGR24,
         4 => ffmpeg_sys::AV_PIX_FMT_YUV422P,
         5 => ffmpeg_sys::AV_PIX_FMT_YUV444P,
+        8 => ffmpeg_sys::AV_PIX_FMT_GRAY8,
+        11 => ffmpeg_sys::AV_PIX_FMT_PAL8,
+        12 => ffmpeg_sys::AV_PIX_FMT_YUVJ420P,
+        13 => ffmpeg_sys::AV_PIX_FMT_YUVJ422P,
+        14 => ffmpeg_sys::AV_PIX_FMT_YUVJ444P,
         17 => ffmpeg_sys::AV_PIX_FMT_UYVY422,
         28 => ffmpeg_sys::AV_PIX_FMT_BGR32,
         30 => ffmpeg_sys::AV_PIX_FMT_RGB32,
-        _ => {
-            println!("Unknown av_pix_fmt value: {}", pix_fmt);
-            panic!();
-        },
+        31 => ffmpeg_sys::AV_PIX_FMT_GRAY16BE,
+        32 => ffmpeg_sys::AV_PIX_FMT_GRAY16LE,
+        _ => return av_pix_fmt_from_descriptors(pix_fmt),
+    };
+
+    Ok(pixel_format)
+}
+
+fn av_pix_fmt_from_descriptors(pix_fmt: i32) -> Result<ffmpeg_sys::AVPixelFormat, FfmpegError> {
+    unsafe {
+        let mut desc = ffmpeg_sys::av_pix_fmt_desc_next(ptr::null());
+        while !desc.is_null() {
+            let pixel_format = ffmpeg_sys::av_pix_fmt_desc_get_id(desc);
+            if pixel_format as i32 == pix_fmt {
+                return Ok(pixel_format);
+            }
+            desc = ffmpeg_sys::av_pix_fmt_desc_next(desc);
+        }
     }
+
+    Err(FfmpegError::from_message(&format!("Unknown av_pix_fmt value: {}", pix_fmt)))
 }
 
 pub unsafe fn convert_frame(
@@ -131,14 +284,18 @@ pub unsafe fn convert_frame(
     let src_w = (*src_frame).width as usize;
     let src_h = (*src_frame).height as usize;
     let dst_frame = try!(make_empty_avframe(src_w, src_h, pixel_format));
-    copy_and_convert_frame(src_frame, dst_frame);
+    if let Err(err) = copy_and_convert_frame(src_frame, dst_frame) {
+        let mut dst_frame = dst_frame;
+        ffmpeg_sys::av_frame_free(&mut dst_frame);
+        return Err(err);
+    }
 
     Ok(dst_frame)
 }
 
-pub unsafe fn copy_and_convert_frame(src_frame : *const ffmpeg_sys::AVFrame, dst_frame : *mut ffmpeg_sys::AVFrame) {
-    let src_format = av_pix_fmt_from_i32((*src_frame).format);
-    let dst_format = av_pix_fmt_from_i32((*dst_frame).format);
+pub unsafe fn copy_and_convert_frame(src_frame : *const ffmpeg_sys::AVFrame, dst_frame : *mut ffmpeg_sys::AVFrame) -> Result<(), FfmpegError> {
+    let src_format = try!(av_pix_fmt_from_i32((*src_frame).format));
+    let dst_format = try!(av_pix_fmt_from_i32((*dst_frame).format));
 
     let src_w = (*src_frame).width;
     let src_h = (*src_frame).height;
@@ -161,6 +318,9 @@ pub unsafe fn copy_and_convert_frame(src_frame : *const ffmpeg_sys::AVFrame, dst
         dst_w, dst_h, dst_format,
         flags, src_filter, dst_filter, param
     );
+    if img_convert_ctx.is_null() {
+        return Err(FfmpegError::from_message("copy_and_convert_frame, sws_getContext: Unsupported conversion."));
+    }
 
     // Convert the image from its native format to RGB
     let src_slice_y = 0;
@@ -174,6 +334,9 @@ pub unsafe fn copy_and_convert_frame(src_frame : *const ffmpeg_sys::AVFrame, dst
         &mut (*dst_frame).data[0],
         &mut (*dst_frame).linesize[0],
     );
+    ffmpeg_sys::sws_freeContext(img_convert_ctx);
+
+    Ok(())
 }
 
 pub unsafe fn make_frame_buffer_vec(width: usize, height: usize, pixel_format: ffmpeg_sys::AVPixelFormat) -> Vec<u8> {
@@ -193,7 +356,7 @@ pub unsafe fn copy_frame_data_to_vec(frame: *mut ffmpeg_sys::AVFrame) -> Result<
     let width = (*frame).width as usize;
     let height = (*frame).height as usize;
 
-    let pixel_format = av_pix_fmt_from_i32((*frame).format);
+    let pixel_format = try!(av_pix_fmt_from_i32((*frame).format));
     let mut data = make_frame_buffer_vec(width, height, pixel_format);
 
     let num_bytes = data.len();
@@ -439,3 +602,73 @@ pub unsafe fn save_frame_to_jpeg(src_frame : *mut ffmpeg_sys::AVFrame, save_fnam
 
     Ok(())
 }
+
+// Encodes a single frame with the given codec, returning the encoded data.
+// The frame is converted to the encoder's pixel format if required.
+pub unsafe fn encode_frame(
+    src_frame : *mut ffmpeg_sys::AVFrame,
+    codec_id : ffmpeg_sys::AVCodecID,
+    output_pixel_format : ffmpeg_sys::AVPixelFormat)
+    -> Result<Vec<u8>, FfmpegError> {
+    let width = (*src_frame).width;
+    let height = (*src_frame).height;
+
+    let encoder = ffmpeg_sys::avcodec_find_encoder(codec_id);
+    if encoder.is_null() {
+        return Err(FfmpegError::from_message("encode_frame, avcodec_find_encoder: encoder not found."));
+    }
+
+    let mut context = ffmpeg_sys::avcodec_alloc_context3(encoder);
+    if context.is_null() {
+        return Err(FfmpegError::from_message("encode_frame, avcodec_alloc_context3: Could not allocate context."));
+    }
+
+    (*context).width = width;
+    (*context).height = height;
+    (*context).pix_fmt = output_pixel_format;
+    (*context).codec_id = codec_id;
+    (*context).codec_type = ffmpeg_sys::AVMEDIA_TYPE_VIDEO;
+    (*context).time_base.num = 1;
+    (*context).time_base.den = 1;
+
+    let open_error = ffmpeg_sys::avcodec_open2(context, encoder, ptr::null_mut());
+    if open_error < 0 {
+        log_av_error("encode_frame, avcodec_open2", open_error);
+        ffmpeg_sys::avcodec_free_context(&mut context);
+        return Err(FfmpegError::from_av_error(open_error));
+    }
+
+    let needs_conversion = (*src_frame).format != output_pixel_format as i32;
+    let mut frame = src_frame;
+    if needs_conversion {
+        frame = try!(convert_frame(src_frame, output_pixel_format));
+    }
+
+    let mut packet : ffmpeg_sys::AVPacket = mem::zeroed();
+    ffmpeg_sys::av_init_packet(&mut packet);
+    packet.data = ptr::null_mut();
+    packet.size = 0;
+
+    let mut got_packet = 0;
+    let encode_error = ffmpeg_sys::avcodec_encode_video2(context, &mut packet, frame, &mut got_packet);
+
+    let result = if encode_error < 0 {
+        log_av_error("encode_frame, avcodec_encode_video2", encode_error);
+        Err(FfmpegError::from_av_error(encode_error))
+    } else if got_packet == 0 || packet.data.is_null() {
+        Err(FfmpegError::from_message("encode_frame: encoded packet is empty."))
+    } else {
+        let packet_data = ::std::slice::from_raw_parts(packet.data, packet.size as usize);
+        Ok(packet_data.to_vec())
+    };
+
+    if needs_conversion {
+        ffmpeg_sys::av_freep((&(*frame).data[0] as *const _) as *mut libc::c_void);
+        ffmpeg_sys::av_frame_free(&mut frame);
+    }
+    ffmpeg_sys::av_free_packet(&mut packet);
+    ffmpeg_sys::avcodec_close(context);
+    ffmpeg_sys::avcodec_free_context(&mut context);
+
+    result
+}
@@ -0,0 +1,229 @@
+extern crate ffmpeg_sys;
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+
+use ffmpeg_camera::ffmpeg_utils;
+use ffmpeg_camera::ffmpeg_utils::FfmpegError;
+use ffmpeg_camera::frame_source::{Frame, FrameSource};
+use ffmpeg_camera::image_nv12;
+use ffmpeg_camera::image::{Image, FromRawParts};
+
+// Frame recordings are stored in a simple container format:
+//     header:  b"OMRFRAME", format version (u32)
+//     frames:  pixel format name length (u32), pixel format name (e.g. b"yuv420p"),
+//              width (u32), height (u32), index (u64),
+//              has timestamp (u8), timestamp in seconds (f64),
+//              data length (u64), raw frame data
+// All values are little-endian.
+const RECORDING_MAGIC : &'static [u8; 8] = b"OMRFRAME";
+const RECORDING_VERSION : u32 = 1;
+
+// Returns the name of the pixel format of frame_source::Frame images (see image_nv12).
+fn frame_pixel_format_name() -> String {
+    ffmpeg_utils::av_pix_fmt_name(image_nv12::PIXEL_FORMAT)
+}
+
+// Returns the number of bytes in a frame of the given size, or None if the size is invalid.
+fn frame_data_len(width : usize, height : usize) -> Option<usize> {
+    if width == 0 || height == 0 || width > i32::max_value() as usize || height > i32::max_value() as usize {
+        return None;
+    }
+
+    let num_bytes = unsafe {
+        ffmpeg_sys::av_image_get_buffer_size(image_nv12::PIXEL_FORMAT, width as i32, height as i32, 1)
+    };
+    if num_bytes < 0 {
+        return None;
+    }
+
+    Some(num_bytes as usize)
+}
+
+fn invalid_data(message : String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn recording_error(operation : &str, err : io::Error) -> FfmpegError {
+    FfmpegError::from_message(&format!("{}: {}", operation, err))
+}
+
+fn write_u32<W : Write>(writer : &mut W, value : u32) -> io::Result<()> {
+    let bytes = [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8];
+    writer.write_all(&bytes)
+}
+
+fn write_u64<W : Write>(writer : &mut W, value : u64) -> io::Result<()> {
+    try!(write_u32(writer, value as u32));
+    write_u32(writer, (value >> 32) as u32)
+}
+
+fn read_u32<R : Read>(reader : &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    try!(reader.read_exact(&mut bytes));
+    Ok(bytes.iter().rev().fold(0, |value, &b| (value << 8) | b as u32))
+}
+
+fn read_u64<R : Read>(reader : &mut R) -> io::Result<u64> {
+    let low = try!(read_u32(reader)) as u64;
+    let high = try!(read_u32(reader)) as u64;
+    Ok((high << 32) | low)
+}
+
+// Writes frames to a recording file.
+pub struct FrameRecorder {
+    writer : BufWriter<File>,
+    num_frames : usize,
+}
+
+impl FrameRecorder {
+    pub fn create(file_name : &str) -> Result<FrameRecorder, FfmpegError> {
+        let file = try!(File::create(file_name).map_err(|err| recording_error("FrameRecorder::create", err)));
+        let mut writer = BufWriter::new(file);
+
+        try!(writer.write_all(RECORDING_MAGIC)
+            .and_then(|_| write_u32(&mut writer, RECORDING_VERSION))
+            .map_err(|err| recording_error("FrameRecorder::create", err)));
+
+        Ok(FrameRecorder {
+            writer: writer,
+            num_frames: 0,
+        })
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.num_frames
+    }
+
+    pub fn write_frame(&mut self, frame : &Frame) -> Result<(), FfmpegError> {
+        try!(self.write_frame_data(frame).map_err(|err| recording_error("FrameRecorder::write_frame", err)));
+        self.num_frames += 1;
+        Ok(())
+    }
+
+    fn write_frame_data(&mut self, frame : &Frame) -> io::Result<()> {
+        let writer = &mut self.writer;
+        let data = &frame.image.local_data;
+
+        let pixel_format = frame_pixel_format_name();
+        try!(write_u32(writer, pixel_format.len() as u32));
+        try!(writer.write_all(pixel_format.as_bytes()));
+        try!(write_u32(writer, frame.image.width() as u32));
+        try!(write_u32(writer, frame.image.height() as u32));
+        try!(write_u64(writer, frame.index as u64));
+        try!(writer.write_all(&[frame.timestamp.is_some() as u8]));
+        try!(write_u64(writer, frame.timestamp.unwrap_or(0.0).to_bits()));
+        try!(write_u64(writer, data.len() as u64));
+        try!(writer.write_all(data));
+
+        // Flush every frame, so that the recording is usable even if the program crashes:
+        writer.flush()
+    }
+}
+
+// Replays the frames of a recording file exactly as they were recorded.
+pub struct RecordingSource {
+    reader : BufReader<File>,
+}
+
+impl RecordingSource {
+    pub fn open(file_name : &str) -> Result<RecordingSource, FfmpegError> {
+        let file = try!(File::open(file_name).map_err(|err| recording_error("RecordingSource::open", err)));
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0u8; 8];
+        try!(reader.read_exact(&mut magic).map_err(|err| recording_error("RecordingSource::open", err)));
+        let version = try!(read_u32(&mut reader).map_err(|err| recording_error("RecordingSource::open", err)));
+
+        if &magic != RECORDING_MAGIC || version != RECORDING_VERSION {
+            let message = format!("RecordingSource::open: '{}' is not a supported frame recording.", file_name);
+            return Err(FfmpegError::from_message(&message));
+        }
+
+        Ok(RecordingSource {
+            reader: reader,
+        })
+    }
+
+    // Reads the next frame, checking that its header is consistent before reading its data.
+    fn read_frame(&mut self) -> io::Result<Frame> {
+        let reader = &mut self.reader;
+
+        // Note: Format names are short, so longer lengths indicate a corrupt recording.
+        let format_len = try!(read_u32(reader)) as usize;
+        if format_len > 64 {
+            return Err(invalid_data(format!("invalid pixel format name length {}", format_len)));
+        }
+        let mut format_bytes = vec![0u8; format_len];
+        try!(reader.read_exact(&mut format_bytes));
+        let pixel_format = String::from_utf8_lossy(&format_bytes).into_owned();
+        if pixel_format != frame_pixel_format_name() {
+            return Err(invalid_data(format!("unsupported pixel format '{}'", pixel_format)));
+        }
+
+        let width = try!(read_u32(reader)) as usize;
+        let height = try!(read_u32(reader)) as usize;
+        let index = try!(read_u64(reader)) as usize;
+
+        let mut has_timestamp = [0u8; 1];
+        try!(reader.read_exact(&mut has_timestamp));
+        let timestamp = f64::from_bits(try!(read_u64(reader)));
+
+        let data_len = try!(read_u64(reader));
+        match frame_data_len(width, height) {
+            Some(expected_len) if expected_len as u64 == data_len => {},
+            _ => return Err(invalid_data(format!("invalid data length {} for a {}x{} frame", data_len, width, height))),
+        }
+        let mut data = vec![0u8; data_len as usize];
+        try!(reader.read_exact(&mut data));
+
+        let frame = Frame {
+            image: image_nv12::Image::from_raw_parts(width, height, data),
+            index: index,
+            timestamp: if has_timestamp[0] != 0 { Some(timestamp) } else { None },
+        };
+
+        Ok(frame)
+    }
+}
+
+impl FrameSource for RecordingSource {
+    fn next_frame(&mut self) -> Result<Frame, FfmpegError> {
+        // Check for the end of the recording (which may only occur between frames):
+        let at_end = try!(self.reader.fill_buf()
+            .map(|buf| buf.is_empty())
+            .map_err(|err| recording_error("RecordingSource::next_frame", err)));
+        if at_end {
+            return Err(FfmpegError::from_av_error(ffmpeg_utils::AVERROR_EOF));
+        }
+
+        self.read_frame().map_err(|err| recording_error("RecordingSource::next_frame", err))
+    }
+}
+
+// Passes through the frames of another source, recording each of them.
+pub struct RecordedSource {
+    source : Box<FrameSource>,
+    recorder : FrameRecorder,
+}
+
+impl RecordedSource {
+    pub fn new(source : Box<FrameSource>, recording_file_name : &str) -> Result<RecordedSource, FfmpegError> {
+        let recorder = try!(FrameRecorder::create(recording_file_name));
+
+        Ok(RecordedSource {
+            source: source,
+            recorder: recorder,
+        })
+    }
+}
+
+impl FrameSource for RecordedSource {
+    fn next_frame(&mut self) -> Result<Frame, FfmpegError> {
+        let frame = try!(self.source.next_frame());
+        try!(self.recorder.write_frame(&frame));
+        Ok(frame)
+    }
+}
@@ -0,0 +1,269 @@
+use std::fs;
+use std::path::PathBuf;
+
+use nalgebra as na;
+
+use ffmpeg_camera::ffmpeg_camera::FfmpegCamera;
+use ffmpeg_camera::camera_spec::{CameraSpec, CaptureBackend};
+use ffmpeg_camera::ffmpeg_utils;
+use ffmpeg_camera::ffmpeg_utils::FfmpegError;
+use ffmpeg_camera::image_file;
+use ffmpeg_camera::frame_recording::RecordingSource;
+use ffmpeg_camera::image_nv12;
+use ffmpeg_camera::image::FromRawParts;
+use geometry::staff::Staff;
+
+// A single frame read from a FrameSource.
+pub struct Frame {
+    pub image : image_nv12::Image,
+
+    // Index of the frame within its source.
+    pub index : usize,
+
+    // Presentation time of the frame in seconds, if known.
+    pub timestamp : Option<f64>,
+}
+
+// A sequence of frames (e.g. from a camera, a video file, or a directory of images).
+// When a source runs out of frames, next_frame returns an error for which
+// FfmpegError::is_end_of_stream is true.
+pub trait FrameSource {
+    fn next_frame(&mut self) -> Result<Frame, FfmpegError>;
+}
+
+fn end_of_stream_error() -> FfmpegError {
+    FfmpegError::from_av_error(ffmpeg_utils::AVERROR_EOF)
+}
+
+impl FrameSource for FfmpegCamera {
+    fn next_frame(&mut self) -> Result<Frame, FfmpegError> {
+        let image = try!(self.get_image_nv12());
+
+        Ok(Frame {
+            image: image,
+            index: self.frame_index().unwrap_or(0),
+            timestamp: self.frame_timestamp(),
+        })
+    }
+}
+
+// Reads the image files in a directory in lexicographic order of their file names.
+pub struct ImageDirectorySource {
+    file_paths : Vec<PathBuf>,
+    next_index : usize,
+
+    // Rate used to assign timestamps to the images.
+    frame_rate : f64,
+}
+
+impl ImageDirectorySource {
+    pub fn new(dir_name : &str, frame_rate : f64) -> Result<ImageDirectorySource, FfmpegError> {
+        let image_extensions = ["png", "jpg", "jpeg", "bmp", "pgm", "ppm"];
+
+        let entries = try!(fs::read_dir(dir_name).map_err(|err| {
+            FfmpegError::from_message(&format!("ImageDirectorySource: {}: {}", dir_name, err))
+        }));
+
+        let mut file_paths = Vec::new();
+        for entry in entries {
+            if let Ok(entry) = entry {
+                let path = entry.path();
+                let is_image = path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .map_or(false, |ext| image_extensions.contains(&ext.to_lowercase().as_str()));
+                if path.is_file() && is_image {
+                    file_paths.push(path);
+                }
+            }
+        }
+        file_paths.sort();
+
+        if file_paths.is_empty() {
+            let message = format!("ImageDirectorySource: no images found in '{}'.", dir_name);
+            return Err(FfmpegError::from_message(&message));
+        }
+
+        Ok(ImageDirectorySource {
+            file_paths: file_paths,
+            next_index: 0,
+            frame_rate: frame_rate,
+        })
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.file_paths.len()
+    }
+}
+
+impl FrameSource for ImageDirectorySource {
+    fn next_frame(&mut self) -> Result<Frame, FfmpegError> {
+        if self.next_index >= self.file_paths.len() {
+            return Err(end_of_stream_error());
+        }
+
+        let index = self.next_index;
+        self.next_index += 1;
+
+        let file_name = self.file_paths[index].to_string_lossy().into_owned();
+        let image = try!(image_file::load_image_nv12(&file_name));
+
+        Ok(Frame {
+            image: image,
+            index: index,
+            timestamp: Some(index as f64 / self.frame_rate),
+        })
+    }
+}
+
+// Generates frames containing evenly spaced, slowly rotating staves on a white background.
+// Useful for exercising the detection pipeline without a camera.
+pub struct SyntheticSource {
+    pub width : usize,
+    pub height : usize,
+    pub frame_rate : f64,
+
+    pub num_staves : usize,
+    pub num_lines : usize,
+    pub line_width : f32,
+    pub space_width : f32,
+
+    // Staff angle in the first frame, and its change per frame (in radians).
+    pub angle : f32,
+    pub angle_step : f32,
+
+    next_index : usize,
+}
+
+impl SyntheticSource {
+    pub fn new(width : usize, height : usize) -> SyntheticSource {
+        SyntheticSource {
+            width: width,
+            height: height,
+            frame_rate: 30.0,
+            num_staves: 4,
+            num_lines: 5,
+            line_width: 2.0,
+            space_width: 8.0,
+            angle: 0.0,
+            angle_step: 0.005,
+            next_index: 0,
+        }
+    }
+
+    fn make_staves(&self, angle : f32) -> Vec<Staff> {
+        let length = self.width as f32 * 0.8;
+        let dir = na::Vector2::new(angle.cos(), angle.sin());
+
+        (0..self.num_staves).map(|i| {
+            let y = self.height as f32 * (i + 1) as f32 / (self.num_staves + 1) as f32;
+            let centre = na::Vector2::new(self.width as f32 / 2.0, y);
+            let a = centre - dir * (length / 2.0);
+            let b = centre + dir * (length / 2.0);
+            Staff::new(a, b, self.line_width, self.space_width, self.num_lines)
+        }).collect()
+    }
+
+    // Renders a YUV420P frame.
+    fn render(&self, angle : f32) -> Vec<u8> {
+        let staves = self.make_staves(angle);
+
+        let luma_size = self.width * self.height;
+        let chroma_size = 2 * ((self.width + 1) / 2) * ((self.height + 1) / 2);
+        let mut data = vec![255; luma_size];
+        data.extend(vec![128; chroma_size]);
+
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let pt = na::Vector2::new(col as f32 + 0.5, row as f32 + 0.5);
+
+                for staff in &staves {
+                    let along = na::dot(&(pt - staff.pos), &staff.dir);
+                    if along < 0.0 || along > staff.length {
+                        continue;
+                    }
+
+                    let across = staff.signed_distance_to_point(&pt);
+                    let on_line = staff.line_offsets().iter().any(|offset| {
+                        (across - offset).abs() <= staff.line_width / 2.0
+                    });
+                    if on_line {
+                        data[row * self.width + col] = 0;
+                    }
+                }
+            }
+        }
+
+        data
+    }
+}
+
+impl FrameSource for SyntheticSource {
+    fn next_frame(&mut self) -> Result<Frame, FfmpegError> {
+        let index = self.next_index;
+        self.next_index += 1;
+
+        let angle = self.angle + self.angle_step * index as f32;
+        let data = self.render(angle);
+
+        Ok(Frame {
+            image: image_nv12::Image::from_raw_parts(self.width, self.height, data),
+            index: index,
+            timestamp: Some(index as f64 / self.frame_rate),
+        })
+    }
+}
+
+// Opens a frame source from a command line specification:
+//     camera                  The best available camera.
+//     camera:<device name>    A specific camera, using the platform's default backend.
+//     avfoundation:<device>   A camera, using a specific backend (e.g. "v4l2:/dev/video1").
+//     v4l2:<device>
+//     lavfi:<filter graph>    An ffmpeg virtual input (e.g. "lavfi:testsrc").
+//     video:<file name>       A video file.
+//     images:<directory>      A directory of image files.
+//     recording:<file name>   A recording made with frame_recording::FrameRecorder.
+//     synthetic               Generated staff images.
+pub fn open_source(spec : &str, video_size : (usize, usize)) -> Result<Box<FrameSource>, FfmpegError> {
+    let (kind, arg) = match spec.find(':') {
+        Some(i) => (&spec[..i], Some(&spec[i+1..])),
+        None => (spec, None),
+    };
+
+    match (kind, arg) {
+        ("camera", None) => {
+            let camera = try!(FfmpegCamera::get_best(video_size));
+            Ok(Box::new(camera))
+        },
+        ("camera", Some(device_name)) => {
+            let backend = CaptureBackend::default_for_platform();
+            let spec = CameraSpec::new(backend, device_name, backend.default_framerate(), video_size);
+            let camera = try!(FfmpegCamera::get_camera(&spec));
+            Ok(Box::new(camera))
+        },
+        ("avfoundation", Some(device)) | ("v4l2", Some(device)) | ("lavfi", Some(device)) => {
+            let backend = CaptureBackend::from_name(kind).unwrap();
+            let spec = CameraSpec::new(backend, device, backend.default_framerate(), video_size);
+            let camera = try!(FfmpegCamera::get_camera(&spec));
+            Ok(Box::new(camera))
+        },
+        ("video", Some(file_name)) => {
+            let video = try!(FfmpegCamera::open_video(file_name));
+            Ok(Box::new(video))
+        },
+        ("images", Some(dir_name)) => {
+            let images = try!(ImageDirectorySource::new(dir_name, 30.0));
+            Ok(Box::new(images))
+        },
+        ("recording", Some(file_name)) => {
+            let recording = try!(RecordingSource::open(file_name));
+            Ok(Box::new(recording))
+        },
+        ("synthetic", None) => {
+            Ok(Box::new(SyntheticSource::new(video_size.0, video_size.1)))
+        },
+        _ => {
+            let message = format!("open_source: invalid frame source '{}'.", spec);
+            Err(FfmpegError::from_message(&message))
+        },
+    }
+}
@@ -1,12 +1,15 @@
 
 use ffmpeg_camera::ffmpeg_utils::FfmpegError;
-use std::io;
+use ffmpeg_camera::image_export;
+use ffmpeg_camera::image_export::ExportError;
 use nalgebra as na;
 
-use std::io::prelude::*;
-use std::fs::OpenOptions;
 use std::cmp;
 
+// Returns None from the enclosing function if the expression is None.
+macro_rules! try_opt {
+    ($e:expr) => (match $e { Some(v) => v, None => return None })
+}
 
 #[derive(Copy, Clone)]
 pub struct Pixel {
@@ -15,14 +18,61 @@ pub struct Pixel {
     pub cr : u8,
 }
 
-pub trait Image : Clone {
+// Interpolation used when sampling an image at a non-integer point.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SampleMode {
+    // The pixel containing the point.
+    Nearest,
+    // Linear interpolation between the 4 nearest pixel centres.
+    Bilinear,
+    // Catmull-Rom interpolation between the 16 nearest pixel centres.
+    Bicubic,
+    // The average over a square of the given side length (in pixels) centred on the point.
+    Area(f32),
+}
+
+// Determines the value of samples that fall outside of an image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BorderPolicy {
+    // Use the nearest pixel on the image border.
+    Clamp,
+    // Use a fixed luma value.
+    Constant(u8),
+    // Report the sample as missing.
+    Missing,
+}
+
+// Catmull-Rom spline weights for the 4 samples around a point with fractional offset t.
+fn cubic_weights(t: f32) -> [f32; 4] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    [
+        0.5 * (-t3 + 2.0*t2 - t),
+        0.5 * (3.0*t3 - 5.0*t2 + 2.0),
+        0.5 * (-3.0*t3 + 4.0*t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
+
+// Images that own their data, and can be constructed from a raw buffer in their pixel format.
+// Note: Views of other images (e.g. SubImage) cannot own data, so do not implement this.
+pub trait FromRawParts : Image {
     fn from_raw_parts(width: usize, height: usize, data: Vec<u8>) -> Self;
+}
 
+pub trait Image : Clone {
     fn width(&self) -> usize; // width in pixels
     fn height(&self) -> usize; // height in pixels
     // fn data(&self) -> &Vec<u8>; // raw data buffer
 
     fn index(&self, col : usize, row : usize) -> Pixel;
+
+    // Returns only the luma (Y) component of a pixel.
+    // Note: Implementations should override this to avoid computing chroma offsets.
+    fn luma(&self, col : usize, row : usize) -> u8 {
+        self.index(col, row).y
+    }
+
     // fn sample_point(&self, pt: na::Vector2<f32>) -> Pixel {
     //     let col = pt[0].floor() as usize;
     //     let row = pt[1].floor() as usize;
@@ -38,6 +88,105 @@ pub trait Image : Clone {
         self.index(col, row)
     }
 
+    fn sample_point_luma(&self, pt: na::Vector2<f32>) -> u8 {
+        let raw_col = pt[0].floor() as usize;
+        let raw_row = pt[1].floor() as usize;
+
+        let col = cmp::max(0, cmp::min(self.width() - 1, raw_col));
+        let row = cmp::max(0, cmp::min(self.height() - 1, raw_row));
+
+        self.luma(col, row)
+    }
+
+    // Returns the luma of a pixel that may lie outside the image, according to the border policy.
+    fn luma_with_border(&self, col : isize, row : isize, border : BorderPolicy) -> Option<f32> {
+        let w = self.width() as isize;
+        let h = self.height() as isize;
+
+        if 0 <= col && col < w && 0 <= row && row < h {
+            return Some(self.luma(col as usize, row as usize) as f32);
+        }
+
+        match border {
+            BorderPolicy::Clamp => {
+                let c = cmp::max(0, cmp::min(w - 1, col));
+                let r = cmp::max(0, cmp::min(h - 1, row));
+                Some(self.luma(c as usize, r as usize) as f32)
+            },
+            BorderPolicy::Constant(value) => Some(value as f32),
+            BorderPolicy::Missing => None,
+        }
+    }
+
+    // Samples the luma at a sub-pixel point (in [0, 255]).
+    // Pixel (col, row) covers the square [col, col+1) x [row, row+1), so its centre lies at
+    // (col + 0.5, row + 0.5).
+    fn sample_luma(&self, pt: na::Vector2<f32>, mode: SampleMode, border: BorderPolicy) -> Option<f32> {
+        match mode {
+            SampleMode::Nearest => {
+                self.luma_with_border(pt[0].floor() as isize, pt[1].floor() as isize, border)
+            },
+            SampleMode::Bilinear => {
+                let x = pt[0] - 0.5;
+                let y = pt[1] - 0.5;
+                let x0 = x.floor();
+                let y0 = y.floor();
+                let fx = x - x0;
+                let fy = y - y0;
+                let (c, r) = (x0 as isize, y0 as isize);
+
+                let p00 = try_opt!(self.luma_with_border(c, r, border));
+                let p10 = try_opt!(self.luma_with_border(c + 1, r, border));
+                let p01 = try_opt!(self.luma_with_border(c, r + 1, border));
+                let p11 = try_opt!(self.luma_with_border(c + 1, r + 1, border));
+
+                let top = p00 * (1.0 - fx) + p10 * fx;
+                let bottom = p01 * (1.0 - fx) + p11 * fx;
+                Some(top * (1.0 - fy) + bottom * fy)
+            },
+            SampleMode::Bicubic => {
+                let x = pt[0] - 0.5;
+                let y = pt[1] - 0.5;
+                let x0 = x.floor();
+                let y0 = y.floor();
+                let wx = cubic_weights(x - x0);
+                let wy = cubic_weights(y - y0);
+                let (c, r) = (x0 as isize, y0 as isize);
+
+                let mut sum = 0.0;
+                for j in 0..4 {
+                    for i in 0..4 {
+                        let p = try_opt!(self.luma_with_border(c + i - 1, r + j - 1, border));
+                        sum += wx[i as usize] * wy[j as usize] * p;
+                    }
+                }
+
+                // Cubic interpolation can overshoot:
+                Some(sum.max(0.0).min(255.0))
+            },
+            SampleMode::Area(size) => {
+                let half = size.max(1.0) / 2.0;
+                let (x0, x1) = (pt[0] - half, pt[0] + half);
+                let (y0, y1) = (pt[1] - half, pt[1] + half);
+
+                // Weight each pixel by the area of its overlap with the sample square:
+                let mut sum = 0.0;
+                let mut weight_sum = 0.0;
+                for r in (y0.floor() as isize)..(y1.ceil() as isize) {
+                    let wy = (y1.min(r as f32 + 1.0) - y0.max(r as f32)).max(0.0);
+                    for c in (x0.floor() as isize)..(x1.ceil() as isize) {
+                        let wx = (x1.min(c as f32 + 1.0) - x0.max(c as f32)).max(0.0);
+                        let p = try_opt!(self.luma_with_border(c, r, border));
+                        sum += wx * wy * p;
+                        weight_sum += wx * wy;
+                    }
+                }
+
+                if weight_sum > 0.0 { Some(sum / weight_sum) } else { None }
+            },
+        }
+    }
+
     fn save_jpeg(&self, save_fname : &str) -> Result<(), FfmpegError>;
 
     fn contains(&self, col : usize, row : usize) -> bool {
@@ -73,23 +222,18 @@ pub trait Image : Clone {
         self.opengl_coords_for_point(na::Vector2::new(px, py))
     }
 
-    fn save_pgm(&self, save_fname: &str) -> io::Result<()> {
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(save_fname)
-            .unwrap();
-        try!(write!(file, "P6\n{} {}\n{}\n", self.width(), self.height(), 255));
-        for y in 0..self.height() {
-            for x in 0..self.width() {
-                let px = self.index(x, y);
-                try!(file.write(&[px.y]));
-                try!(file.write(&[px.cb]));
-                try!(file.write(&[px.cr]));
-            }
-        }
-        try!(writeln!(file, ""));
-        Ok(())
+    // Saves the luma of the image as a greyscale PGM (P5) file.
+    fn save_pgm(&self, save_fname: &str) -> Result<(), ExportError> {
+        image_export::save_pgm(self, save_fname)
+    }
+
+    // Saves the image as an RGB PPM (P6) file.
+    fn save_ppm(&self, save_fname: &str) -> Result<(), ExportError> {
+        image_export::save_ppm(self, save_fname)
+    }
+
+    // Saves the image as a lossless RGB PNG file.
+    fn save_png(&self, save_fname: &str) -> Result<(), ExportError> {
+        image_export::save_png(self, save_fname)
     }
 }
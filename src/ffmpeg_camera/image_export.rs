@@ -0,0 +1,141 @@
+extern crate ffmpeg_sys;
+
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+
+use ffmpeg_camera::ffmpeg_utils;
+use ffmpeg_camera::ffmpeg_utils::FfmpegError;
+use ffmpeg_camera::image::{Image, Pixel};
+
+// An error that occurred while saving an image.
+#[derive(Debug)]
+pub enum ExportError {
+    Io(io::Error),
+    Ffmpeg(FfmpegError),
+}
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExportError::Io(ref err) => write!(f, "{{ExportError (io): {}}}", err),
+            ExportError::Ffmpeg(ref err) => write!(f, "{{ExportError (ffmpeg): {}}}", err),
+        }
+    }
+}
+impl error::Error for ExportError {
+    fn description(&self) -> &str {
+        match *self {
+            ExportError::Io(ref err) => err.description(),
+            ExportError::Ffmpeg(ref err) => err.description(),
+        }
+    }
+}
+impl From<io::Error> for ExportError {
+    fn from(err: io::Error) -> ExportError {
+        ExportError::Io(err)
+    }
+}
+impl From<FfmpegError> for ExportError {
+    fn from(err: FfmpegError) -> ExportError {
+        ExportError::Ffmpeg(err)
+    }
+}
+
+fn clamp_to_u8(value: f32) -> u8 {
+    value.round().max(0.0).min(255.0) as u8
+}
+
+// Converts a full range (JPEG) YCbCr pixel to RGB.
+// Note: This matches the conversion used by the shaders in drawing::glsl_functions.
+pub fn ycbcr_to_rgb(px: Pixel) -> [u8; 3] {
+    let y = px.y as f32;
+    let cb = px.cb as f32 - 128.0;
+    let cr = px.cr as f32 - 128.0;
+
+    [
+        clamp_to_u8(y + 1.402 * cr),
+        clamp_to_u8(y - 0.34414 * cb - 0.71414 * cr),
+        clamp_to_u8(y + 1.772 * cb),
+    ]
+}
+
+// Returns the luma of the image as a packed width x height buffer.
+pub fn to_grey_bytes<I: Image>(image: &I) -> Vec<u8> {
+    let mut data = Vec::with_capacity(image.width() * image.height());
+    for row in 0..image.height() {
+        for col in 0..image.width() {
+            data.push(image.luma(col, row));
+        }
+    }
+
+    data
+}
+
+// Returns the image as a packed RGB24 buffer.
+pub fn to_rgb_bytes<I: Image>(image: &I) -> Vec<u8> {
+    let mut data = Vec::with_capacity(image.width() * image.height() * 3);
+    for row in 0..image.height() {
+        for col in 0..image.width() {
+            data.extend_from_slice(&ycbcr_to_rgb(image.index(col, row)));
+        }
+    }
+
+    data
+}
+
+// Saves the luma of the image as a binary greyscale PGM (P5) file.
+pub fn save_pgm<I: Image>(image: &I, save_fname: &str) -> Result<(), ExportError> {
+    let mut file = try!(File::create(save_fname));
+    try!(write!(file, "P5\n{} {}\n{}\n", image.width(), image.height(), 255));
+    try!(file.write_all(&to_grey_bytes(image)));
+    Ok(())
+}
+
+// Saves the image as a binary RGB PPM (P6) file.
+pub fn save_ppm<I: Image>(image: &I, save_fname: &str) -> Result<(), ExportError> {
+    let mut file = try!(File::create(save_fname));
+    try!(write!(file, "P6\n{} {}\n{}\n", image.width(), image.height(), 255));
+    try!(file.write_all(&to_rgb_bytes(image)));
+    Ok(())
+}
+
+// Saves the image as an RGB PNG file, using ffmpeg's PNG encoder.
+pub fn save_png<I: Image>(image: &I, save_fname: &str) -> Result<(), ExportError> {
+    let rgb_data = to_rgb_bytes(image);
+
+    let png_data = unsafe {
+        let mut rgb_frame = try!(
+            ffmpeg_utils::make_avframe(
+                image.width(),
+                image.height(),
+                ffmpeg_sys::AV_PIX_FMT_RGB24,
+                &rgb_data
+            )
+        );
+        let result = ffmpeg_utils::encode_frame(rgb_frame, ffmpeg_sys::AV_CODEC_ID_PNG, ffmpeg_sys::AV_PIX_FMT_RGB24);
+        ffmpeg_sys::av_frame_free(&mut rgb_frame);
+        try!(result)
+    };
+
+    let mut file = try!(File::create(save_fname));
+    try!(file.write_all(&png_data));
+    Ok(())
+}
+
+// Saves the image in the format given by the file name's extension (.pgm, .ppm, .png, or .jpg).
+pub fn save_image<I: Image>(image: &I, save_fname: &str) -> Result<(), ExportError> {
+    let extension = save_fname.rsplit('.').next().unwrap_or("").to_lowercase();
+
+    match extension.as_str() {
+        "pgm" => save_pgm(image, save_fname),
+        "ppm" => save_ppm(image, save_fname),
+        "png" => save_png(image, save_fname),
+        "jpg" | "jpeg" => image.save_jpeg(save_fname).map_err(ExportError::from),
+        _ => {
+            let message = format!("save_image: unsupported file type '{}'.", save_fname);
+            Err(ExportError::Ffmpeg(FfmpegError::from_message(&message)))
+        },
+    }
+}
@@ -0,0 +1,41 @@
+extern crate ffmpeg_sys;
+use std::path::Path;
+
+use ffmpeg_camera::ffmpeg_camera::FfmpegCamera;
+use ffmpeg_camera::ffmpeg_utils::FfmpegError;
+use ffmpeg_camera::image_nv12;
+use ffmpeg_camera::image_uyvy;
+use ffmpeg_camera::image_ycbcr;
+use ffmpeg_camera::image;
+
+// Loads still images (e.g. PNG, JPEG, BMP, and PGM files) from disk.
+// Decoding is performed by ffmpeg, which detects the file format from the file's contents and
+// extension.
+
+fn open_image_file(file_name : &str) -> Result<FfmpegCamera, FfmpegError> {
+    if !Path::new(file_name).is_file() {
+        let message = format!("open_image_file: '{}' is not a file.", file_name);
+        return Err(FfmpegError::from_message(&message));
+    }
+
+    FfmpegCamera::open_file(file_name)
+}
+
+pub fn load_image<I : image::FromRawParts>(file_name : &str, target_format : ffmpeg_sys::AVPixelFormat)
+    -> Result<I, FfmpegError> {
+    let mut source = try!(open_image_file(file_name));
+    source.get_image::<I>(target_format)
+}
+
+pub fn load_image_nv12(file_name : &str) -> Result<image_nv12::Image, FfmpegError> {
+    load_image::<image_nv12::Image>(file_name, image_nv12::PIXEL_FORMAT)
+}
+
+pub fn load_image_uyvy(file_name : &str) -> Result<image_uyvy::Image, FfmpegError> {
+    load_image::<image_uyvy::Image>(file_name, ffmpeg_sys::AV_PIX_FMT_UYVY422)
+}
+
+pub fn load_image_ycbcr(file_name : &str) -> Result<image_ycbcr::Image, FfmpegError> {
+    let mut source = try!(open_image_file(file_name));
+    source.get_image_ycbcr()
+}
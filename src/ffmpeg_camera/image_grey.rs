@@ -0,0 +1,92 @@
+extern crate ffmpeg_sys;
+use ffmpeg_camera::ffmpeg_utils;
+use ffmpeg_camera::ffmpeg_utils::FfmpegError;
+use ffmpeg_camera::image;
+use std::borrow::Cow;
+
+// An 8-bit greyscale image.
+// The pixel data may either be owned, or borrowed from another image (e.g. the luma plane of an
+// image_nv12::Image), which avoids copying frames that are only used for detection.
+#[derive(Clone)]
+pub struct Image<'a> {
+    pub width : usize, // width in pixels
+    pub height : usize, // height in pixels
+    pub data : Cow<'a, [u8]>, // grey8 data buffer
+}
+
+impl<'a> Image<'a> {
+    pub fn from_luma_plane(width: usize, height: usize, data: &'a [u8]) -> Image<'a> {
+        if data.len() < width * height {
+            panic!("from_luma_plane: Data buffer is too small.");
+        }
+
+        Image {
+            width: width,
+            height: height,
+            data: Cow::Borrowed(&data[..width * height]),
+        }
+    }
+
+    // Returns an image that owns its data.
+    pub fn into_owned(self) -> Image<'static> {
+        Image {
+            width: self.width,
+            height: self.height,
+            data: Cow::Owned(self.data.into_owned()),
+        }
+    }
+}
+
+impl<'a> image::FromRawParts for Image<'a> {
+    fn from_raw_parts(width: usize, height: usize, data: Vec<u8>) -> Image<'a> {
+        Image {
+            width: width,
+            height: height,
+            data: Cow::Owned(data),
+        }
+    }
+}
+
+impl<'a> image::Image for Image<'a> {
+    fn width(&self) -> usize {
+        self.width
+    }
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn index(&self, col : usize, row : usize) -> image::Pixel {
+        image::Pixel {
+            y: self.luma(col, row),
+            cb: 128,
+            cr: 128,
+        }
+    }
+
+    fn luma(&self, col : usize, row : usize) -> u8 {
+        if !self.contains(col, row) {
+            panic!("Image index out of bounds.");
+        }
+
+        unsafe {
+            *self.data.get_unchecked(row * self.width + col)
+        }
+    }
+
+    fn save_jpeg(&self, save_fname : &str) -> Result<(), FfmpegError> {
+        unsafe {
+            let mut grey_frame = try!(
+                ffmpeg_utils::make_avframe(
+                    self.width,
+                    self.height,
+                    ffmpeg_sys::AV_PIX_FMT_GRAY8,
+                    &self.data
+                )
+            );
+            try!(ffmpeg_utils::save_frame_to_jpeg(grey_frame, save_fname));
+            ffmpeg_sys::av_frame_free(&mut grey_frame);
+        }
+
+        Ok(())
+    }
+}
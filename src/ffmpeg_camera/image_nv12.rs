@@ -7,6 +7,10 @@ use ffmpeg_camera::ffmpeg_utils::FfmpegError;
 use std;
 use ffmpeg_camera;
 use ffmpeg_camera::image::Pixel;
+use ffmpeg_camera::image_grey;
+
+// Pixel format of the image data (planar Y, then subsampled Cb and Cr planes).
+pub const PIXEL_FORMAT : ffmpeg_sys::AVPixelFormat = ffmpeg_sys::AV_PIX_FMT_YUV420P;
 
 #[derive(Clone)]
 pub struct Image {
@@ -17,7 +21,15 @@ pub struct Image {
     pub af_grey : af::Array,
 }
 
-impl ffmpeg_camera::Image for Image {
+impl Image {
+    // Returns a greyscale image that borrows this image's luma plane.
+    pub fn luma_image(&self) -> image_grey::Image {
+        let luma_bytes = self.width * self.height;
+        image_grey::Image::from_luma_plane(self.width, self.height, &self.local_data[..luma_bytes])
+    }
+}
+
+impl ffmpeg_camera::FromRawParts for Image {
     fn from_raw_parts(width: usize, height: usize, data: Vec<u8>) -> Image {
         // Data is an array containing raw interleaved YCbCrA data.
         // let shape = [1, height as u64, width as u64, 1];
@@ -35,7 +47,9 @@ impl ffmpeg_camera::Image for Image {
             af_grey: img_nv12,
         }
     }
+}
 
+impl ffmpeg_camera::Image for Image {
     fn width(&self) -> usize {
         self.width
     }
@@ -72,6 +86,16 @@ impl ffmpeg_camera::Image for Image {
         // }
     }
 
+    fn luma(&self, col : usize, row : usize) -> u8 {
+        if !self.contains(col, row) {
+            panic!("Image index out of bounds.");
+        }
+
+        unsafe {
+            *self.local_data.get_unchecked(row * self.width + col)
+        }
+    }
+
     // Based on: https://lists.libav.org/pipermail/libav-user/2010-August/005159.html
     fn save_jpeg(&self, save_fname : &str) -> Result<(), FfmpegError> {
         unsafe {
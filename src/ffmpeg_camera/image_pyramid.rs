@@ -0,0 +1,92 @@
+use nalgebra as na;
+
+use ffmpeg_camera::image::{Image, FromRawParts, SampleMode, BorderPolicy};
+use ffmpeg_camera::image_grey;
+
+// A sequence of successively downsampled copies of an image.
+// Level 0 is the original image. Level i has been downsampled by scale_factor^i.
+pub struct ImagePyramid<'a, I : 'a + Image> {
+    pub base : &'a I,
+
+    // Greyscale levels 1, 2, ..., num_levels - 1.
+    pub levels : Vec<image_grey::Image<'static>>,
+
+    pub scale_factor : f32,
+}
+
+// Downsamples the luma of an image by the given factor, averaging over the area covered by each
+// output pixel.
+pub fn downsample<I : Image>(image : &I, factor : f32) -> image_grey::Image<'static> {
+    let width = (image.width() as f32 / factor).floor() as usize;
+    let height = (image.height() as f32 / factor).floor() as usize;
+
+    let mut data = Vec::with_capacity(width * height);
+    for row in 0..height {
+        for col in 0..width {
+            let pt = na::Vector2::new((col as f32 + 0.5) * factor, (row as f32 + 0.5) * factor);
+            let luma = image.sample_luma(pt, SampleMode::Area(factor), BorderPolicy::Clamp).unwrap();
+            data.push(luma.round() as u8);
+        }
+    }
+
+    image_grey::Image::from_raw_parts(width, height, data)
+}
+
+impl<'a, I : Image> ImagePyramid<'a, I> {
+    // Builds up to num_levels levels, stopping early if a level would be smaller than min_size
+    // pixels in either dimension.
+    pub fn new(image : &'a I, num_levels : usize, scale_factor : f32, min_size : usize) -> ImagePyramid<'a, I> {
+        if scale_factor <= 1.0 {
+            panic!("ImagePyramid::new: scale_factor must be greater than 1.");
+        }
+
+        let mut levels : Vec<image_grey::Image<'static>> = Vec::new();
+        for i in 1..num_levels {
+            let factor = scale_factor.powi(i as i32);
+            let next_w = (image.width() as f32 / factor).floor() as usize;
+            let next_h = (image.height() as f32 / factor).floor() as usize;
+            if next_w < min_size || next_h < min_size {
+                break;
+            }
+
+            // Downsample from the previous level, which is cheaper than from the base image:
+            let level = match levels.last() {
+                Some(prev) => downsample(prev, scale_factor),
+                None => downsample(image, scale_factor),
+            };
+            levels.push(level);
+        }
+
+        ImagePyramid {
+            base: image,
+            levels: levels,
+            scale_factor: scale_factor,
+        }
+    }
+
+    pub fn num_levels(&self) -> usize {
+        self.levels.len() + 1
+    }
+
+    // Factors by which x and y coordinates in the given level must be multiplied to obtain
+    // coordinates in the base image.
+    // Note: Level sizes are rounded down to whole pixels, so the two factors usually differ
+    // slightly from each other (and from scale_factor^level).
+    pub fn level_scale(&self, level : usize) -> na::Vector2<f32> {
+        if level == 0 {
+            return na::Vector2::new(1.0, 1.0);
+        }
+
+        let level_image = &self.levels[level - 1];
+        na::Vector2::new(
+            self.base.width() as f32 / level_image.width() as f32,
+            self.base.height() as f32 / level_image.height() as f32
+        )
+    }
+
+    // Maps a point in the given level to base image coordinates.
+    pub fn to_base_coords(&self, level : usize, pt : na::Vector2<f32>) -> na::Vector2<f32> {
+        let scale = self.level_scale(level);
+        na::Vector2::new(pt[0] * scale[0], pt[1] * scale[1])
+    }
+}
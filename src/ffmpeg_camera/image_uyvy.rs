@@ -18,7 +18,7 @@ pub struct Image {
     pub data : Vec<u8>, // uyvy422 data buffer
 }
 
-impl image::Image for Image {
+impl image::FromRawParts for Image {
     fn from_raw_parts(width: usize, height: usize, data: Vec<u8>) -> Image {
         Image {
             width: width,
@@ -26,7 +26,9 @@ impl image::Image for Image {
             data: data,
         }
     }
+}
 
+impl image::Image for Image {
     fn width(&self) -> usize {
         self.width
     }
@@ -64,6 +66,19 @@ impl image::Image for Image {
         // }
     }
 
+    fn luma(&self, col : usize, row : usize) -> u8 {
+        if !self.contains(col, row) {
+            panic!("Image index out of bounds.");
+        }
+
+        // u y v y u y v y ...
+        let y_i = row * self.width * 2 + col*2 + 1;
+
+        unsafe {
+            *self.data.get_unchecked(y_i)
+        }
+    }
+
     // Based on: https://lists.libav.org/pipermail/libav-user/2010-August/005159.html
     fn save_jpeg(&self, save_fname : &str) -> Result<(), FfmpegError> {
         unsafe {
@@ -0,0 +1,115 @@
+use nalgebra as na;
+use std::cmp;
+
+use ffmpeg_camera::ffmpeg_utils::FfmpegError;
+use ffmpeg_camera::image;
+use ffmpeg_camera::image_export;
+use ffmpeg_camera::image::{Image, FromRawParts};
+use ffmpeg_camera::image_grey;
+
+// A rectangular region of an image, in pixels.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Region {
+    pub x : usize,
+    pub y : usize,
+    pub width : usize,
+    pub height : usize,
+}
+
+impl Region {
+    pub fn new(x : usize, y : usize, width : usize, height : usize) -> Region {
+        Region {
+            x: x,
+            y: y,
+            width: width,
+            height: height,
+        }
+    }
+
+    // Returns the part of the region that lies within an image of the given size.
+    pub fn clipped_to(&self, width : usize, height : usize) -> Region {
+        let x = cmp::min(self.x, width);
+        let y = cmp::min(self.y, height);
+        Region {
+            x: x,
+            y: y,
+            width: cmp::min(self.x + self.width, width) - x,
+            height: cmp::min(self.y + self.height, height) - y,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    // The offset of the region's origin from its parent's origin.
+    pub fn offset(&self) -> na::Vector2<f32> {
+        na::Vector2::new(self.x as f32, self.y as f32)
+    }
+}
+
+// A view of a rectangular region of another image, without copying its data.
+// Pixel (0, 0) of the view is pixel (region.x, region.y) of the parent.
+#[derive(Clone)]
+pub struct SubImage<'a, I : 'a + Image> {
+    pub parent : &'a I,
+    pub region : Region,
+}
+
+impl<'a, I : Image> SubImage<'a, I> {
+    // Note: The region is clipped to the bounds of the parent image. Regions that are empty once
+    // clipped (e.g. that lie outside the parent) are rejected.
+    pub fn new(parent : &'a I, region : Region) -> Result<SubImage<'a, I>, FfmpegError> {
+        let clipped = region.clipped_to(parent.width(), parent.height());
+        if clipped.is_empty() {
+            let message = format!("SubImage::new: Region {:?} does not overlap the {}x{} image.",
+                region, parent.width(), parent.height());
+            return Err(FfmpegError::from_message(&message));
+        }
+
+        Ok(SubImage {
+            parent: parent,
+            region: clipped,
+        })
+    }
+
+    pub fn to_parent_coords(&self, pt : na::Vector2<f32>) -> na::Vector2<f32> {
+        pt + self.region.offset()
+    }
+
+    pub fn to_parent_index(&self, index : [usize; 2]) -> [usize; 2] {
+        [index[0] + self.region.x, index[1] + self.region.y]
+    }
+}
+
+impl<'a, I : Image> image::Image for SubImage<'a, I> {
+    fn width(&self) -> usize {
+        self.region.width
+    }
+    fn height(&self) -> usize {
+        self.region.height
+    }
+
+    fn index(&self, col : usize, row : usize) -> image::Pixel {
+        if !self.contains(col, row) {
+            panic!("Image index out of bounds.");
+        }
+
+        self.parent.index(col + self.region.x, row + self.region.y)
+    }
+
+    fn luma(&self, col : usize, row : usize) -> u8 {
+        if !self.contains(col, row) {
+            panic!("Image index out of bounds.");
+        }
+
+        self.parent.luma(col + self.region.x, row + self.region.y)
+    }
+
+    // Note: Only the luma of the view is saved.
+    fn save_jpeg(&self, save_fname : &str) -> Result<(), FfmpegError> {
+        let data = image_export::to_grey_bytes(self);
+        let grey_image = image_grey::Image::from_raw_parts(self.width(), self.height(), data);
+        grey_image.save_jpeg(save_fname)
+    }
+}
@@ -98,10 +98,34 @@ impl Image {
             af_data: data_array,
         }
     }
+
+    // Creates an image from planar YUV444 data (i.e. a Y plane, followed by a Cb plane, followed
+    // by a Cr plane, with no padding).
+    pub fn from_planar_yuv444(width: usize, height: usize, planar_data: &[u8]) -> Image {
+        let plane_size = width * height;
+        if planar_data.len() < plane_size * 3 {
+            panic!("from_planar_yuv444: Data buffer is too small.");
+        }
+
+        let (y_plane, cbcr_planes) = planar_data.split_at(plane_size);
+        let (cb_plane, cr_plane) = cbcr_planes.split_at(plane_size);
+
+        // Interleave the planes:
+        // i.e. [YYY CbCb CrCr] -> [YCbCrA YCbCrA YCbCrA]
+        let mut data = Vec::with_capacity(plane_size * 4);
+        for i in 0..plane_size {
+            data.push(y_plane[i]);
+            data.push(cb_plane[i]);
+            data.push(cr_plane[i]);
+            data.push(255);
+        }
+
+        <Image as ffmpeg_camera::FromRawParts>::from_raw_parts(width, height, data)
+    }
 }
 
 
-impl ffmpeg_camera::Image for Image {
+impl ffmpeg_camera::FromRawParts for Image {
     fn from_raw_parts(width: usize, height: usize, data: Vec<u8>) -> Image {
         // Data is an array containing raw interleaved YCbCrA data.
         // let shape = [height as u64, width as u64, 4, 1];
@@ -119,7 +143,9 @@ impl ffmpeg_camera::Image for Image {
             af_data: img_32bit,
         }
     }
+}
 
+impl ffmpeg_camera::Image for Image {
     fn width(&self) -> usize {
         self.width
     }
@@ -156,6 +182,16 @@ impl ffmpeg_camera::Image for Image {
         // }
     }
 
+    fn luma(&self, col : usize, row : usize) -> u8 {
+        if !self.contains(col, row) {
+            panic!("Image index out of bounds.");
+        }
+
+        unsafe {
+            *self.local_data.get_unchecked((row * self.width + col) * 4)
+        }
+    }
+
     // Based on: https://lists.libav.org/pipermail/libav-user/2010-August/005159.html
     fn save_jpeg(&self, save_fname : &str) -> Result<(), FfmpegError> {
         unsafe {
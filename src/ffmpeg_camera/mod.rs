@@ -1,12 +1,24 @@
 pub mod ffmpeg_camera;
+pub mod camera_spec;
+pub mod device_list;
 pub mod ffmpeg_utils;
+pub mod image_file;
+pub mod frame_source;
+pub mod frame_recording;
 pub mod image_ycbcr;
 pub mod image_uyvy;
 pub mod image_nv12;
+pub mod image_grey;
+pub mod image_pyramid;
+pub mod image_view;
+pub mod image_export;
 pub mod image;
 pub mod af_image;
 pub mod to_texture;
 
 pub use self::image::Image;
+pub use self::image::FromRawParts;
 pub use self::af_image::AfImage;
 pub use self::to_texture::ToTexture;
+pub use self::frame_source::FrameSource;
+pub use self::camera_spec::{CameraSpec, CaptureBackend};
@@ -68,3 +68,21 @@ impl ToTexture for super::image_nv12::Image {
         texture
     }
 }
+
+impl<'a> ToTexture for super::image_grey::Image<'a> {
+    fn to_texture(&self, display: &glium::Display) -> glium::texture::Texture2d {
+        let cow: Cow<[_]> = Cow::Borrowed(&self.data);
+
+        let img_w = self.width as u32;
+        let img_h = self.height as u32;
+        let raw_image = glium::texture::RawImage2d {
+            data: cow,
+            width: img_w,
+            height: img_h,
+            format: glium::texture::ClientFormat::U8
+        };
+        let texture = glium::texture::Texture2d::new(display, raw_image).unwrap();
+
+        texture
+    }
+}
@@ -0,0 +1,330 @@
+// Bezier curve functions based on http://pomax.github.io/bezierinfo/
+// Ported from the prototype in web/Curves.elm.
+
+use std::f32;
+use nalgebra as na;
+use nalgebra::Norm;
+use math;
+
+// Times closer than this are treated as the same when checking that a fit is well constrained.
+const MIN_TIME_SEPARATION : f32 = 1e-3;
+
+fn bezier2(w0: f32, w1: f32, w2: f32, t: f32) -> f32 {
+    let t2 = t*t;
+    let mt = 1.0 - t;
+    let mt2 = mt*mt;
+
+    w0*mt2 + w1*2.0*mt*t + w2*t2
+}
+
+fn bezier3(w0: f32, w1: f32, w2: f32, w3: f32, t: f32) -> f32 {
+    let t2 = t*t;
+    let t3 = t*t2;
+    let mt = 1.0 - t;
+    let mt2 = mt*mt;
+    let mt3 = mt*mt2;
+
+    w0*mt3 + w1*3.0*mt2*t + w2*3.0*mt*t2 + w3*t3
+}
+
+// The cubic Bernstein polynomials at t (i.e. the weight of each control point).
+fn bezier3_basis(t: f64) -> [f64; 4] {
+    let mt = 1.0 - t;
+    [mt*mt*mt, 3.0*mt*mt*t, 3.0*mt*t*t, t*t*t]
+}
+
+// A parametric curve through an image.
+pub trait Curve {
+    // The curve is defined for times in [0, max_time()].
+    fn max_time(&self) -> f32;
+
+    fn point_at(&self, t: f32) -> na::Vector2<f32>;
+    fn derivative_at(&self, t: f32) -> na::Vector2<f32>;
+    fn second_derivative_at(&self, t: f32) -> na::Vector2<f32>;
+
+    fn tangent_at(&self, t: f32) -> na::Vector2<f32> {
+        na::normalize(&self.derivative_at(t))
+    }
+
+    // Note: Uses the same convention as Staff::normal.
+    fn normal_at(&self, t: f32) -> na::Vector2<f32> {
+        let tangent = self.tangent_at(t);
+        na::Vector2::new(tangent[1], -tangent[0])
+    }
+
+    // Returns num_samples + 1 points, evenly spaced in time along the curve.
+    fn samples(&self, num_samples: usize) -> Vec<na::Vector2<f32>> {
+        (0..num_samples + 1)
+            .map(|i| self.point_at(self.max_time() * i as f32 / num_samples as f32))
+            .collect()
+    }
+
+    // Returns the time of the closest point on the curve to pt.
+    // The curve is sampled to find the approximate closest point, which is then refined with a few
+    // Newton iterations.
+    fn closest_time(&self, pt: &na::Vector2<f32>) -> f32 {
+        let num_samples = 50 * (self.max_time().ceil() as usize).max(1);
+        let step = self.max_time() / num_samples as f32;
+
+        let mut best_t = 0.0;
+        let mut best_dist = f32::INFINITY;
+        for i in 0..num_samples + 1 {
+            let t = step * i as f32;
+            let dist = (self.point_at(t) - *pt).norm_squared();
+            if dist < best_dist {
+                best_t = t;
+                best_dist = dist;
+            }
+        }
+
+        // Minimise |point_at(t) - pt|^2:
+        let mut t = best_t;
+        for _ in 0..3 {
+            let offset = self.point_at(t) - *pt;
+            let d1 = self.derivative_at(t);
+            let d2 = self.second_derivative_at(t);
+
+            let gradient = na::dot(&offset, &d1);
+            let curvature = na::dot(&d1, &d1) + na::dot(&offset, &d2);
+            if curvature <= 0.0 {
+                break;
+            }
+
+            t = (t - gradient / curvature).max(0.0).min(self.max_time());
+        }
+
+        if (self.point_at(t) - *pt).norm_squared() < best_dist { t } else { best_t }
+    }
+
+    fn distance_to_point(&self, pt: &na::Vector2<f32>) -> f32 {
+        let t = self.closest_time(pt);
+        (self.point_at(t) - *pt).norm()
+    }
+
+    fn signed_distance_to_point(&self, pt: &na::Vector2<f32>) -> f32 {
+        let t = self.closest_time(pt);
+        na::dot(&(*pt - self.point_at(t)), &self.normal_at(t))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CubicBezier {
+    pub points: [na::Vector2<f32>; 4],
+}
+
+impl CubicBezier {
+    pub fn new(a: na::Vector2<f32>, b: na::Vector2<f32>, c: na::Vector2<f32>, d: na::Vector2<f32>) -> CubicBezier {
+        CubicBezier {
+            points: [a, b, c, d],
+        }
+    }
+
+    // A straight line from a to b.
+    pub fn straight(a: na::Vector2<f32>, b: na::Vector2<f32>) -> CubicBezier {
+        CubicBezier::new(a, a + (b - a) / 3.0, a + (b - a) * 2.0 / 3.0, b)
+    }
+
+    // Least squares fit of a curve to points, where each point is at the corresponding time, and
+    // each squared error is scaled by the point's weight.
+    // Returns None if fewer than 4 points have distinct times.
+    pub fn fit_to_points_at_times(points: &[na::Vector2<f32>], times: &[f32], weights: &[f32]) -> Option<CubicBezier> {
+        // The basis functions are only independent over at least 4 distinct times:
+        let mut sorted_times = times.to_vec();
+        sorted_times.sort_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+        sorted_times.dedup_by(|a, b| (*a - *b).abs() < MIN_TIME_SEPARATION);
+        if sorted_times.len() < 4 {
+            return None;
+        }
+
+        // Solve B*P = Q for the control points P, with each row scaled by the square root of its
+        // weight to weight its squared residual:
+        let mut basis_rows = Vec::with_capacity(points.len());
+        let mut qx = Vec::with_capacity(points.len());
+        let mut qy = Vec::with_capacity(points.len());
+        for ((pt, &t), &w) in points.iter().zip(times.iter()).zip(weights.iter()) {
+            let w = (w as f64).max(0.0).sqrt();
+            let basis = bezier3_basis(t as f64);
+            basis_rows.push(basis.iter().map(|b| w * b).collect::<Vec<f64>>());
+            qx.push(w * pt[0] as f64);
+            qy.push(w * pt[1] as f64);
+        }
+
+        let xs = match math::solve_least_squares(&basis_rows, &qx) {
+            Some(xs) => xs,
+            None => return None,
+        };
+        let ys = match math::solve_least_squares(&basis_rows, &qy) {
+            Some(ys) => ys,
+            None => return None,
+        };
+
+        let control_point = |i: usize| na::Vector2::new(xs[i] as f32, ys[i] as f32);
+        Some(CubicBezier::new(control_point(0), control_point(1), control_point(2), control_point(3)))
+    }
+
+    // Fits a curve to points (see order_points), weighting each point's squared error.
+    pub fn fit_to_points(points: &[na::Vector2<f32>], weights: &[f32]) -> Option<CubicBezier> {
+        let (order, times) = match order_points(points, weights) {
+            Some(ordering) => ordering,
+            None => return None,
+        };
+
+        let sorted_points : Vec<na::Vector2<f32>> = order.iter().map(|&i| points[i]).collect();
+        let sorted_weights : Vec<f32> = order.iter().map(|&i| weights[i]).collect();
+
+        CubicBezier::fit_to_points_at_times(&sorted_points, &times, &sorted_weights)
+    }
+}
+
+impl Curve for CubicBezier {
+    fn max_time(&self) -> f32 {
+        1.0
+    }
+
+    fn point_at(&self, t: f32) -> na::Vector2<f32> {
+        let (a, b, c, d) = (self.points[0], self.points[1], self.points[2], self.points[3]);
+        na::Vector2::new(
+            bezier3(a[0], b[0], c[0], d[0], t),
+            bezier3(a[1], b[1], c[1], d[1], t)
+        )
+    }
+
+    fn derivative_at(&self, t: f32) -> na::Vector2<f32> {
+        let (a, b, c, d) = (self.points[0], self.points[1], self.points[2], self.points[3]);
+        let (dw0, dw1, dw2) = ((b - a) * 3.0, (c - b) * 3.0, (d - c) * 3.0);
+        na::Vector2::new(
+            bezier2(dw0[0], dw1[0], dw2[0], t),
+            bezier2(dw0[1], dw1[1], dw2[1], t)
+        )
+    }
+
+    fn second_derivative_at(&self, t: f32) -> na::Vector2<f32> {
+        let (a, b, c, d) = (self.points[0], self.points[1], self.points[2], self.points[3]);
+        let ddw0 = (c - b * 2.0 + a) * 6.0;
+        let ddw1 = (d - c * 2.0 + b) * 6.0;
+        ddw0 * (1.0 - t) + ddw1 * t
+    }
+}
+
+// Orders points along their principal axis, and assigns each a time in [0, 1] proportional to its
+// projection onto the axis.
+// Note: The prototype sorted points by x and spaced their times evenly, which fails for vertical
+// staves and unevenly spaced points. Distances along a polyline through the points would zig-zag
+// with the noise across the staff, so are not used either.
+// Returns None if there are fewer than 4 points, or they all project to the same position.
+pub fn order_points(points: &[na::Vector2<f32>], weights: &[f32]) -> Option<(Vec<usize>, Vec<f32>)> {
+    if points.len() < 4 {
+        return None;
+    }
+
+    let axis = match math::principal_axis(points, weights) {
+        Some((_, axis)) => axis,
+        None => return None,
+    };
+
+    let projections : Vec<f32> = points.iter().map(|pt| na::dot(pt, &axis)).collect();
+    let mut order : Vec<usize> = (0..points.len()).collect();
+    order.sort_by(|&a, &b| projections[a].partial_cmp(&projections[b]).unwrap_or(::std::cmp::Ordering::Equal));
+
+    let min_proj = projections[order[0]];
+    let extent = projections[order[order.len() - 1]] - min_proj;
+    if extent <= 0.0 {
+        return None;
+    }
+
+    let times = order.iter().map(|&i| (projections[i] - min_proj) / extent).collect();
+    Some((order, times))
+}
+
+// A piecewise cubic Bezier curve. Times in [i, i + 1] lie on segment i.
+#[derive(Debug, Clone)]
+pub struct BezierSpline {
+    pub segments: Vec<CubicBezier>,
+}
+
+impl BezierSpline {
+    pub fn new(segments: Vec<CubicBezier>) -> BezierSpline {
+        if segments.is_empty() {
+            panic!("BezierSpline::new: A spline requires at least one segment.");
+        }
+
+        BezierSpline {
+            segments: segments,
+        }
+    }
+
+    // Returns the segment containing a time, and the time within that segment.
+    fn segment_at(&self, t: f32) -> (&CubicBezier, f32) {
+        let last = self.segments.len() - 1;
+        let i = (t.max(0.0).floor() as usize).min(last);
+        (&self.segments[i], t - i as f32)
+    }
+
+    // Fits a spline with the given number of segments to points (see order_points).
+    // Each segment is fitted to the points in its share of the spline, then the segments are
+    // joined smoothly, by meeting at the mean of their end points with collinear control points.
+    // Returns None if any segment has fewer than 4 points at distinct times (e.g. where there is a
+    // gap in the points), in which case fewer segments may still be fitted.
+    pub fn fit_to_points(points: &[na::Vector2<f32>], weights: &[f32], num_segments: usize) -> Option<BezierSpline> {
+        let (order, times) = match order_points(points, weights) {
+            Some(ordering) => ordering,
+            None => return None,
+        };
+
+        let num_segments = num_segments.max(1);
+        let mut segments = Vec::with_capacity(num_segments);
+        for i in 0..num_segments {
+            let mut segment_points = Vec::new();
+            let mut segment_times = Vec::new();
+            let mut segment_weights = Vec::new();
+            for (&j, &t) in order.iter().zip(times.iter()) {
+                let local_t = t * num_segments as f32 - i as f32;
+                if 0.0 <= local_t && local_t <= 1.0 {
+                    segment_points.push(points[j]);
+                    segment_times.push(local_t);
+                    segment_weights.push(weights[j]);
+                }
+            }
+
+            match CubicBezier::fit_to_points_at_times(&segment_points, &segment_times, &segment_weights) {
+                Some(segment) => segments.push(segment),
+                None => return None,
+            }
+        }
+
+        for i in 1..num_segments {
+            let knot = (segments[i - 1].points[3] + segments[i].points[0]) / 2.0;
+            let in_len = (segments[i - 1].points[3] - segments[i - 1].points[2]).norm();
+            let out_len = (segments[i].points[1] - segments[i].points[0]).norm();
+            let dir = na::normalize(&(segments[i].points[1] - segments[i - 1].points[2]));
+
+            segments[i - 1].points[2] = knot - dir * in_len;
+            segments[i - 1].points[3] = knot;
+            segments[i].points[0] = knot;
+            segments[i].points[1] = knot + dir * out_len;
+        }
+
+        Some(BezierSpline::new(segments))
+    }
+}
+
+impl Curve for BezierSpline {
+    fn max_time(&self) -> f32 {
+        self.segments.len() as f32
+    }
+
+    fn point_at(&self, t: f32) -> na::Vector2<f32> {
+        let (segment, local_t) = self.segment_at(t);
+        segment.point_at(local_t)
+    }
+
+    fn derivative_at(&self, t: f32) -> na::Vector2<f32> {
+        let (segment, local_t) = self.segment_at(t);
+        segment.derivative_at(local_t)
+    }
+
+    fn second_derivative_at(&self, t: f32) -> na::Vector2<f32> {
+        let (segment, local_t) = self.segment_at(t);
+        segment.second_derivative_at(local_t)
+    }
+}
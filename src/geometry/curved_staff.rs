@@ -0,0 +1,174 @@
+use nalgebra as na;
+use nalgebra::Norm;
+use geometry::curve::{Curve, BezierSpline};
+use geometry::staff::StaffPath;
+
+// Samples per curve segment used to measure distances along the centre line.
+const ARC_SAMPLES_PER_SEGMENT : usize = 64;
+
+// A staff with a curved centre line (e.g. on a photo of a page from a bound book).
+// Like Staff, times are distances along the centre line, so that staves can be followed in steps
+// of a fixed number of pixels.
+#[derive(Debug, Clone)]
+pub struct CurvedStaff {
+    pub centreline: BezierSpline,
+    pub line_width: f32,
+    pub space_width: f32,
+    pub num_lines: usize,
+
+    // The distance along the centre line to the start of the staff, and the length of the staff.
+    pub start: f32,
+    pub length: f32,
+
+    // The distance along the centre line at evenly spaced curve times.
+    arc_lengths: Vec<f32>,
+}
+
+impl CurvedStaff {
+    // A staff along the whole of the given centre line.
+    pub fn new(centreline: BezierSpline, line_width: f32, space_width: f32, num_lines: usize) -> CurvedStaff {
+        let num_samples = ARC_SAMPLES_PER_SEGMENT * centreline.segments.len();
+        let points = centreline.samples(num_samples);
+
+        let mut arc_lengths = Vec::with_capacity(points.len());
+        arc_lengths.push(0.0);
+        for i in 1..points.len() {
+            let dist = arc_lengths[i - 1] + (points[i] - points[i - 1]).norm();
+            arc_lengths.push(dist);
+        }
+        let length = arc_lengths[arc_lengths.len() - 1];
+
+        CurvedStaff {
+            centreline: centreline,
+            line_width: line_width,
+            space_width: space_width,
+            num_lines: num_lines,
+            start: 0.0,
+            length: length,
+            arc_lengths: arc_lengths,
+        }
+    }
+
+    // Converts a time along the staff to a time along the centre line curve.
+    pub fn curve_time(&self, time: f32) -> f32 {
+        let dist = self.start + time;
+        let step = self.centreline.max_time() / (self.arc_lengths.len() - 1) as f32;
+
+        // Find the samples on either side, and interpolate between them:
+        let i = match self.arc_lengths.binary_search_by(|d| d.partial_cmp(&dist).unwrap_or(::std::cmp::Ordering::Less)) {
+            Ok(i) => return i as f32 * step,
+            Err(i) => i,
+        };
+        if i == 0 {
+            return 0.0;
+        }
+        if i >= self.arc_lengths.len() {
+            return self.centreline.max_time();
+        }
+
+        let (d0, d1) = (self.arc_lengths[i - 1], self.arc_lengths[i]);
+        let frac = if d1 > d0 { (dist - d0) / (d1 - d0) } else { 0.0 };
+        (i as f32 - 1.0 + frac) * step
+    }
+
+    // Converts a time along the centre line curve to a time along the staff.
+    pub fn staff_time(&self, curve_time: f32) -> f32 {
+        let step = self.centreline.max_time() / (self.arc_lengths.len() - 1) as f32;
+        let pos = (curve_time / step).max(0.0);
+        let i = (pos.floor() as usize).min(self.arc_lengths.len() - 2);
+        let frac = pos - i as f32;
+
+        let dist = self.arc_lengths[i] * (1.0 - frac) + self.arc_lengths[i + 1] * frac;
+        dist - self.start
+    }
+
+    // Returns the time along the staff of the closest point on the centre line to a point.
+    pub fn closest_time(&self, point: &na::Vector2<f32>) -> f32 {
+        self.staff_time(self.centreline.closest_time(point))
+    }
+
+    pub fn tangent_at_time(&self, time: f32) -> na::Vector2<f32> {
+        self.centreline.tangent_at(self.curve_time(time))
+    }
+
+    pub fn distance_to_point(&self, point: &na::Vector2<f32>) -> f32 {
+        self.centreline.distance_to_point(point)
+    }
+
+    pub fn signed_distance_to_point(&self, point: &na::Vector2<f32>) -> f32 {
+        self.centreline.signed_distance_to_point(point)
+    }
+
+    // Returns the staff moved by the given offset (e.g. from a SubImage to its parent).
+    pub fn translated(&self, offset: na::Vector2<f32>) -> CurvedStaff {
+        let mut staff = self.clone();
+        for segment in &mut staff.centreline.segments {
+            for point in &mut segment.points {
+                *point = *point + offset;
+            }
+        }
+        staff
+    }
+}
+
+impl StaffPath for CurvedStaff {
+    fn line_width(&self) -> f32 {
+        self.line_width
+    }
+
+    fn space_width(&self) -> f32 {
+        self.space_width
+    }
+
+    fn num_lines(&self) -> usize {
+        self.num_lines
+    }
+
+    fn length(&self) -> f32 {
+        self.length
+    }
+
+    fn point_at_time(&self, time: f32) -> na::Vector2<f32> {
+        self.centreline.point_at(self.curve_time(time))
+    }
+
+    fn normal_at_time(&self, time: f32) -> na::Vector2<f32> {
+        self.centreline.normal_at(self.curve_time(time))
+    }
+
+    // Note: Unlike a straight Staff, a curve cannot be extended beyond its ends, so the times are
+    // always within [0, length].
+    fn screen_entry_exit_times(&self, width: f32, height: f32) -> (f32, f32) {
+        let is_in_image = |pt: na::Vector2<f32>| 0.0 <= pt[0] && pt[0] <= width && 0.0 <= pt[1] && pt[1] <= height;
+
+        let step = 1.0;
+        let mut entry = None;
+        let mut exit = 0.0;
+        let mut t = 0.0;
+        while t <= self.length {
+            if is_in_image(self.point_at_time(t)) {
+                if entry.is_none() {
+                    entry = Some(t);
+                }
+                exit = t;
+            }
+            t += step;
+        }
+
+        match entry {
+            Some(entry) => (entry, exit),
+            None => (0.0, 0.0),
+        }
+    }
+
+    fn sub_staff(&self, start_time: f32, end_time: f32) -> CurvedStaff {
+        let start = (self.start + start_time).max(0.0);
+        let end = (self.start + end_time).min(self.arc_lengths[self.arc_lengths.len() - 1]);
+
+        CurvedStaff {
+            start: start,
+            length: (end - start).max(0.0),
+            .. self.clone()
+        }
+    }
+}
@@ -1,5 +1,7 @@
 pub mod staff;
 pub mod rotated_rectangle;
+pub mod curve;
+pub mod curved_staff;
 
 pub use self::rotated_rectangle::RotatedRectangle;
 
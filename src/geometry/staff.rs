@@ -3,19 +3,20 @@ use nalgebra as na;
 use nalgebra::Norm;
 use geometry as gm;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Staff {
     pub pos: na::Vector2<f32>,
     pub dir: na::Vector2<f32>,
     pub length: f32,
     pub line_width: f32,
     pub space_width: f32,
+    pub num_lines: usize,
 }
 
 impl Staff {
 
     #[inline(never)]
-    pub fn new(a: na::Vector2<f32>, b: na::Vector2<f32>, line_width: f32, space_width: f32) -> Staff {
+    pub fn new(a: na::Vector2<f32>, b: na::Vector2<f32>, line_width: f32, space_width: f32, num_lines: usize) -> Staff {
         let dir = b - a;
         let norm_dir = na::normalize(&dir);
         let length = dir.norm();
@@ -26,6 +27,19 @@ impl Staff {
             length: length,
             line_width: line_width,
             space_width: space_width,
+            num_lines: num_lines,
+        }
+    }
+
+    // Returns the staff moved by the given offset (e.g. from a SubImage to its parent).
+    pub fn translated(&self, offset: na::Vector2<f32>) -> Staff {
+        Staff {
+            pos: self.pos + offset,
+            dir: self.dir,
+            length: self.length,
+            line_width: self.line_width,
+            space_width: self.space_width,
+            num_lines: self.num_lines,
         }
     }
 
@@ -33,6 +47,18 @@ impl Staff {
         self.line_width + self.space_width
     }
 
+    // The distance across the staff, from the outer edge of the first line to that of the last.
+    pub fn height(&self) -> f32 {
+        let num_spaces = if self.num_lines > 0 { self.num_lines - 1 } else { 0 };
+        self.line_width * self.num_lines as f32 + self.space_width * num_spaces as f32
+    }
+
+    // Signed distances of the centre of each line from the centre of the staff.
+    pub fn line_offsets(&self) -> Vec<f32> {
+        let mid = (self.num_lines as f32 - 1.0) / 2.0;
+        (0..self.num_lines).map(|i| (i as f32 - mid) * self.line_sep()).collect()
+    }
+
     pub fn normal(&self) -> na::Vector2<f32> {
         na::Vector2::new(self.dir[1], -self.dir[0])
     }
@@ -92,3 +118,91 @@ impl Staff {
         line.screen_entry_exit_times(width, height)
     }
 }
+
+// A staff that can be followed along its length, which may be straight (Staff) or curved
+// (CurvedStaff). Times are distances along the centre line, from the start of the staff.
+pub trait StaffPath : Sized {
+    fn line_width(&self) -> f32;
+    fn space_width(&self) -> f32;
+    fn num_lines(&self) -> usize;
+    fn length(&self) -> f32;
+
+    fn point_at_time(&self, time: f32) -> na::Vector2<f32>;
+    fn normal_at_time(&self, time: f32) -> na::Vector2<f32>;
+
+    // The range of times over which the staff's centre line lies within an image.
+    fn screen_entry_exit_times(&self, width: f32, height: f32) -> (f32, f32);
+
+    // Returns the part of the staff between two times.
+    fn sub_staff(&self, start_time: f32, end_time: f32) -> Self;
+
+    fn line_sep(&self) -> f32 {
+        self.line_width() + self.space_width()
+    }
+
+    fn height(&self) -> f32 {
+        let num_spaces = if self.num_lines() > 0 { self.num_lines() - 1 } else { 0 };
+        self.line_width() * self.num_lines() as f32 + self.space_width() * num_spaces as f32
+    }
+
+    // Points across the staff at the given time, centred on the centre line.
+    fn perpendicular_samples(&self, time: f32, num_samples: usize, sample_sep: f32) -> Vec<na::Vector2<f32>> {
+        let p_t = self.point_at_time(time);
+        let normal = self.normal_at_time(time);
+
+        let mid = (num_samples as f32 - 1.0) / 2.0;
+        (0..num_samples).map(|i| p_t + normal * (sample_sep * (i as f32 - mid))).collect()
+    }
+}
+
+impl StaffPath for Staff {
+    fn line_width(&self) -> f32 {
+        self.line_width
+    }
+
+    fn space_width(&self) -> f32 {
+        self.space_width
+    }
+
+    fn num_lines(&self) -> usize {
+        self.num_lines
+    }
+
+    fn length(&self) -> f32 {
+        self.length
+    }
+
+    fn point_at_time(&self, time: f32) -> na::Vector2<f32> {
+        Staff::point_at_time(self, time)
+    }
+
+    fn normal_at_time(&self, _: f32) -> na::Vector2<f32> {
+        self.normal()
+    }
+
+    fn screen_entry_exit_times(&self, width: f32, height: f32) -> (f32, f32) {
+        Staff::screen_entry_exit_times(self, width, height)
+    }
+
+    fn sub_staff(&self, start_time: f32, end_time: f32) -> Staff {
+        Staff::new(
+            Staff::point_at_time(self, start_time),
+            Staff::point_at_time(self, end_time),
+            self.line_width,
+            self.space_width,
+            self.num_lines
+        )
+    }
+
+    fn line_sep(&self) -> f32 {
+        Staff::line_sep(self)
+    }
+
+    fn height(&self) -> f32 {
+        Staff::height(self)
+    }
+
+    fn perpendicular_samples(&self, time: f32, num_samples: usize, sample_sep: f32) -> Vec<na::Vector2<f32>> {
+        Staff::perpendicular_samples(self, time, num_samples, sample_sep)
+    }
+}
@@ -21,7 +21,7 @@ fn least_squares_solve(a: &rgsl::MatrixF64, b: &rgsl::VectorF64) -> rgsl::Vector
     // println!("qr: {:?}", qr);
     // println!("tau: {:?}", tau);
 
-    let x = rgsl::VectorF64::new(a.size1()).unwrap();
+    let x = rgsl::VectorF64::new(a.size2()).unwrap();
     let residual = rgsl::VectorF64::new(a.size1()).unwrap();
 
     let solve_error = rgsl::linear_algebra::QR_lssolve(&qr, &tau, &b, &x, &residual);
@@ -34,18 +34,30 @@ fn least_squares_solve(a: &rgsl::MatrixF64, b: &rgsl::VectorF64) -> rgsl::Vector
 }
 
 pub fn fit_line(points: &Vec<na::Vector2<f32>>) -> gm::Line {
+    let weights = vec![1.0; points.len()];
+    fit_line_weighted(points, &weights)
+}
+
+// Weighted least squares fit, where each point's squared residual is scaled by its weight.
+pub fn fit_line_weighted(points: &Vec<na::Vector2<f32>>, weights: &[f32]) -> gm::Line {
+    if weights.len() != points.len() {
+        panic!("fit_line_weighted: Expected one weight per point.");
+    }
+
     let m = points.len();
     let n = 2;
     let x = rgsl::MatrixF64::new(m, 2).unwrap();
     let y = rgsl::MatrixF64::new(m, 1).unwrap();
 
-    for (i, pt) in points.iter().enumerate() {
-        x.set(i, 0, 1.0 as f64);
-        x.set(i, 1, pt[0] as f64);
-        y.set(i, 0, pt[1] as f64);
+    // Scaling each row by the square root of its weight weights its squared residual:
+    for (i, (pt, weight)) in points.iter().zip(weights.iter()).enumerate() {
+        let w = (*weight as f64).max(0.0).sqrt();
+        x.set(i, 0, w);
+        x.set(i, 1, w * pt[0] as f64);
+        y.set(i, 0, w * pt[1] as f64);
     }
 
-    // Solve X.t()*X*beta = X.t()*y
+    // Solve X.t()*W*X*beta = X.t()*W*y
 
     let mut gramian = rgsl::MatrixF64::new(n, n).unwrap();
     let mut xt_y_mat = rgsl::MatrixF64::new(n, 1).unwrap();
@@ -99,3 +111,75 @@ pub fn fit_line(points: &Vec<na::Vector2<f32>>) -> gm::Line {
     let l1 = na::Vector2::<f32>::new(1.0, b + m);
     gm::Line::new(l0, l1)
 }
+
+// Returns the weighted mean of the points, and the direction in which they are most spread out
+// (i.e. left to right for a horizontal staff), pointing towards positive x.
+// Returns None if there are no points or the weights sum to zero.
+pub fn principal_axis(points: &[na::Vector2<f32>], weights: &[f32]) -> Option<(na::Vector2<f32>, na::Vector2<f32>)> {
+    let weight_sum = weights.iter().fold(0.0, |sum, &w| sum + w);
+    if points.is_empty() || weight_sum <= 0.0 {
+        return None;
+    }
+
+    let mean = points.iter().zip(weights.iter()).fold(na::Vector2::new(0.0, 0.0), |sum, (pt, &w)| sum + *pt * w) / weight_sum;
+    let (mut cxx, mut cxy, mut cyy) = (0.0, 0.0, 0.0);
+    for (pt, &w) in points.iter().zip(weights.iter()) {
+        let d = *pt - mean;
+        cxx += w * d[0] * d[0];
+        cxy += w * d[0] * d[1];
+        cyy += w * d[1] * d[1];
+    }
+    let angle = 0.5 * (2.0 * cxy).atan2(cxx - cyy);
+    let mut axis = na::Vector2::new(angle.cos(), angle.sin());
+    if axis[0] < 0.0 {
+        axis = -axis;
+    }
+
+    Some((mean, axis))
+}
+
+// Weighted total least squares fit, which minimises the weighted squared perpendicular distances
+// of the points from the line, so is unbiased for lines at any angle (unlike fit_line_weighted).
+// Returns None if there are no points or the weights sum to zero.
+pub fn fit_line_total_weighted(points: &[na::Vector2<f32>], weights: &[f32]) -> Option<gm::Line> {
+    if weights.len() != points.len() {
+        panic!("fit_line_total_weighted: Expected one weight per point.");
+    }
+
+    match principal_axis(points, weights) {
+        Some((mean, axis)) => Some(gm::Line::new(mean, mean + axis)),
+        None => None,
+    }
+}
+
+// Least squares solution of a*x = b, where a is given as a list of rows, and has at least as many
+// rows as columns.
+// Returns None if the solution is not finite.
+// Note: Callers must ensure that a has full column rank.
+pub fn solve_least_squares(a: &[Vec<f64>], b: &[f64]) -> Option<Vec<f64>> {
+    let m = a.len();
+    if m == 0 || b.len() != m {
+        return None;
+    }
+    let n = a[0].len();
+    if n == 0 || m < n {
+        return None;
+    }
+
+    let a_mat = rgsl::MatrixF64::new(m, n).unwrap();
+    let b_vec = rgsl::VectorF64::new(m).unwrap();
+    for (i, row) in a.iter().enumerate() {
+        for (j, &value) in row.iter().enumerate() {
+            a_mat.set(i, j, value);
+        }
+        b_vec.set(i, b[i]);
+    }
+
+    let x_vec = least_squares_solve(&a_mat, &b_vec);
+    let x : Vec<f64> = (0..n).map(|j| x_vec.get(j)).collect();
+    if x.iter().any(|v| !v.is_finite()) {
+        return None;
+    }
+
+    Some(x)
+}
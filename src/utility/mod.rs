@@ -1,6 +1,7 @@
 extern crate core;
 
 pub mod af_util;
+pub mod thread_pool;
 use std::mem;
 
 use self::core::default::Default;
@@ -0,0 +1,188 @@
+extern crate libc;
+
+use std::cell::Cell;
+use std::cmp;
+use std::fmt;
+use std::mem;
+use std::panic;
+use std::sync::{Arc, Mutex, Once, ONCE_INIT};
+use std::sync::mpsc;
+use std::thread;
+
+type Job = Box<FnMut() + Send + 'static>;
+
+// Whether the current thread is a worker of some pool (see ThreadPool::map_chunks).
+thread_local!(static IS_WORKER : Cell<bool> = Cell::new(false));
+
+// The pool shared by default (see ThreadPool::default).
+static mut SHARED_POOL : *const ThreadPool = 0 as *const ThreadPool;
+static SHARED_POOL_INIT : Once = ONCE_INIT;
+
+// Persistent worker threads, which run jobs until every handle to the pool has been dropped.
+struct Workers {
+    // Note: Workers exit once the sender is dropped.
+    sender : Mutex<mpsc::Sender<Job>>,
+}
+
+impl Workers {
+    fn spawn(num_threads: usize) -> Workers {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for _ in 0..num_threads {
+            let receiver = receiver.clone();
+            thread::spawn(move || {
+                IS_WORKER.with(|is_worker| is_worker.set(true));
+
+                loop {
+                    // Note: The receiver is unlocked before the job is run.
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        Ok(mut job) => job(),
+                        Err(_) => break,
+                    }
+                }
+            });
+        }
+
+        Workers {
+            sender: Mutex::new(sender),
+        }
+    }
+}
+
+// Allows a job to borrow from the caller of map_chunks.
+// This is only safe if the caller waits for the job to be dropped before the borrows end.
+unsafe fn erase_job_lifetime<'a>(job: Box<FnMut() + Send + 'a>) -> Job {
+    mem::transmute(job)
+}
+
+// Returns the number of online processors (at least 1).
+fn num_processors() -> usize {
+    let num_processors = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+    cmp::max(1, num_processors) as usize
+}
+
+// Maps work over a fixed number of persistent worker threads.
+// Work is split into contiguous chunks, one per thread, and results are returned in the order of
+// the input, so the results never depend on the number of threads or on how they are scheduled.
+// Note: Clones share the same workers, so pools are cheap to pass around in parameters.
+#[derive(Clone)]
+pub struct ThreadPool {
+    num_threads : usize,
+
+    // None if work is run on the calling thread.
+    workers : Option<Arc<Workers>>,
+}
+
+impl fmt::Debug for ThreadPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ThreadPool {{ num_threads: {} }}", self.num_threads)
+    }
+}
+
+impl Default for ThreadPool {
+    // Uses a pool shared by the whole process, with one thread per processor.
+    // Note: Parameters are often created per frame, so this avoids spawning threads per frame.
+    fn default() -> ThreadPool {
+        unsafe {
+            SHARED_POOL_INIT.call_once(|| {
+                SHARED_POOL = Box::into_raw(Box::new(ThreadPool::new(num_processors())));
+            });
+            (*SHARED_POOL).clone()
+        }
+    }
+}
+
+impl ThreadPool {
+    // Spawns a new pool of num_threads workers.
+    pub fn new(num_threads: usize) -> ThreadPool {
+        let num_threads = cmp::max(1, num_threads);
+
+        ThreadPool {
+            num_threads: num_threads,
+            workers: if num_threads > 1 { Some(Arc::new(Workers::spawn(num_threads))) } else { None },
+        }
+    }
+
+    // Runs all work on the calling thread.
+    pub fn single_threaded() -> ThreadPool {
+        ThreadPool::new(1)
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.num_threads
+    }
+
+    // Applies f to contiguous chunks of the items, returning the result for each chunk in order.
+    // Note: Work submitted from a worker thread (e.g. nested calls) is run on that thread, as
+    // waiting for other workers from a worker could deadlock the pool.
+    pub fn map_chunks<T, R, F>(&self, items: &[T], f: F) -> Vec<R>
+        where T: Sync
+            , R: Send
+            , F: Fn(&[T]) -> R + Sync {
+
+        if items.is_empty() {
+            return Vec::new();
+        }
+
+        let max_chunks = cmp::min(self.num_threads, items.len());
+        let chunk_size = (items.len() + max_chunks - 1) / max_chunks;
+        let num_chunks = (items.len() + chunk_size - 1) / chunk_size;
+
+        let is_worker = IS_WORKER.with(|is_worker| is_worker.get());
+        let workers = match self.workers {
+            Some(ref workers) if num_chunks > 1 && !is_worker => workers,
+            _ => return items.chunks(chunk_size).map(|chunk| f(chunk)).collect(),
+        };
+
+        let (result_sender, result_receiver) = mpsc::channel();
+        {
+            let f = &f;
+            let sender = workers.sender.lock().unwrap_or_else(|err| err.into_inner());
+            for (i, chunk) in items.chunks(chunk_size).enumerate() {
+                let result_sender = result_sender.clone();
+                let job = unsafe {
+                    erase_job_lifetime(Box::new(move || {
+                        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| f(chunk)));
+                        let _ = result_sender.send((i, result));
+                    }))
+                };
+
+                // Note: A job that can't be sent is dropped immediately, ending its borrows.
+                let _ = sender.send(job);
+            }
+        }
+        drop(result_sender);
+
+        // Wait until every job has been dropped (i.e. every result sender), as jobs borrow the
+        // items and f.
+        let mut results : Vec<Option<thread::Result<R>>> = (0..num_chunks).map(|_| None).collect();
+        for (i, result) in result_receiver.iter() {
+            results[i] = Some(result);
+        }
+
+        results.into_iter().map(|result| {
+            match result {
+                Some(Ok(result)) => result,
+                Some(Err(err)) => panic::resume_unwind(err),
+                None => panic!("ThreadPool: Worker thread stopped."),
+            }
+        }).collect()
+    }
+
+    // Applies f to each item, returning the results in order.
+    pub fn map<T, R, F>(&self, items: &[T], f: F) -> Vec<R>
+        where T: Sync
+            , R: Send
+            , F: Fn(&T) -> R + Sync {
+
+        let chunk_results = self.map_chunks(items, |chunk| chunk.iter().map(&f).collect::<Vec<R>>());
+
+        let mut results = Vec::with_capacity(items.len());
+        for chunk_result in chunk_results {
+            results.extend(chunk_result);
+        }
+        results
+    }
+}